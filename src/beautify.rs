@@ -0,0 +1,243 @@
+use std::{env, fs, path::PathBuf};
+use image::{imageops::{self, FilterType}, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+use crate::crop;
+
+pub const PRESETS_FILE_NAME: &str = "rust_screenshot_beautify.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Background {
+    Solid,
+    Gradient,
+    Image,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AspectRatio {
+    Free,
+    Widescreen,
+    Standard,
+    Square,
+    TwitterCard,
+}
+
+impl AspectRatio {
+    pub const ALL: [AspectRatio; 5] = [
+        AspectRatio::Free,
+        AspectRatio::Widescreen,
+        AspectRatio::Standard,
+        AspectRatio::Square,
+        AspectRatio::TwitterCard,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AspectRatio::Free => "Free",
+            AspectRatio::Widescreen => "16:9",
+            AspectRatio::Standard => "4:3",
+            AspectRatio::Square => "1:1",
+            AspectRatio::TwitterCard => "Twitter card",
+        }
+    }
+
+    /// Width divided by height
+    pub fn ratio(&self) -> Option<f32> {
+        match self {
+            AspectRatio::Free => None,
+            AspectRatio::Widescreen => Some(16.0 / 9.0),
+            AspectRatio::Standard => Some(4.0 / 3.0),
+            AspectRatio::Square => Some(1.0),
+            AspectRatio::TwitterCard => Some(crop::TWITTER_CARD.0 as f32 / crop::TWITTER_CARD.1 as f32),
+        }
+    }
+}
+
+/// How the screenshot is framed. Colors are unmultiplied RGBA.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Style {
+    pub padding: u32,
+    pub background: Background,
+    pub color: [u8; 4],
+    pub gradient_color: [u8; 4],
+    /// Degrees, 0 goes from left to right, 90 from top to bottom
+    pub gradient_angle: f32,
+    pub background_image: String,
+    pub corner_radius: u32,
+    pub shadow_offset: [i32; 2],
+    pub shadow_blur: f32,
+    pub shadow_color: [u8; 4],
+    pub aspect_ratio: AspectRatio,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style {
+            padding: 64,
+            background: Background::Gradient,
+            color: [88, 101, 242, 255],
+            gradient_color: [235, 69, 158, 255],
+            gradient_angle: 45.0,
+            background_image: String::new(),
+            corner_radius: 12,
+            shadow_offset: [0, 12],
+            shadow_blur: 16.0,
+            shadow_color: [0, 0, 0, 110],
+            aspect_ratio: AspectRatio::Free,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Preset {
+    pub name: String,
+    pub style: Style,
+}
+
+fn mix(a: [u8; 4], b: [u8; 4], t: f32) -> Rgba<u8> {
+    let mut mixed = [0u8; 4];
+    for i in 0..4 {
+        mixed[i] = (a[i] as f32 + (b[i] as f32 - a[i] as f32) * t).round() as u8;
+    }
+    Rgba(mixed)
+}
+
+fn background(style: &Style, width: u32, height: u32) -> Result<RgbaImage, String> {
+    match style.background {
+        Background::Solid => Ok(RgbaImage::from_pixel(width, height, Rgba(style.color))),
+        Background::Gradient => {
+            let (sin, cos) = style.gradient_angle.to_radians().sin_cos();
+            // Project the corners on the gradient direction to map the whole canvas to 0..1
+            let corners = [(0.0, 0.0), (width as f32, 0.0), (0.0, height as f32), (width as f32, height as f32)];
+            let projections: Vec<f32> = corners.iter().map(|(x, y)| x * cos + y * sin).collect();
+            let min = projections.iter().cloned().fold(f32::MAX, f32::min);
+            let max = projections.iter().cloned().fold(f32::MIN, f32::max);
+            let range = (max - min).max(1.0);
+
+            Ok(RgbaImage::from_fn(width, height, |x, y| {
+                let t = ((x as f32 + 0.5) * cos + (y as f32 + 0.5) * sin - min) / range;
+                mix(style.color, style.gradient_color, t.clamp(0.0, 1.0))
+            }))
+        },
+        Background::Image => {
+            let image = image::open(&style.background_image).map_err(|e| format!("Cannot open the background image {} -> {:?}", style.background_image, e))?;
+            Ok(image.resize_to_fill(width, height, FilterType::Triangle).to_rgba8())
+        }
+    }
+}
+
+/// Makes the corners transparent, with a one pixel antialiased edge.
+pub fn round_corners(image: &mut RgbaImage, radius: u32) {
+    let (width, height) = image.dimensions();
+    let radius = radius.min(width / 2).min(height / 2);
+    if radius == 0 {
+        return;
+    }
+
+    let r = radius as f32;
+    for y in 0..height {
+        for x in 0..width {
+            // Distance from the center of the nearest corner circle, only inside the corner squares
+            let cx = if x < radius { r } else if x >= width - radius { (width - radius) as f32 } else { continue };
+            let cy = if y < radius { r } else if y >= height - radius { (height - radius) as f32 } else { continue };
+            let distance = ((x as f32 + 0.5 - cx).powi(2) + (y as f32 + 0.5 - cy).powi(2)).sqrt();
+            let coverage = (r - distance + 0.5).clamp(0.0, 1.0);
+
+            let pixel = image.get_pixel_mut(x, y);
+            pixel[3] = (pixel[3] as f32 * coverage).round() as u8;
+        }
+    }
+}
+
+/// Shadow of the image's alpha, blurred, with a transparent margin big enough for the blur.
+/// Returns the shadow and the margin.
+fn shadow(image: &RgbaImage, color: [u8; 4], blur: f32) -> (RgbaImage, u32) {
+    let margin = (blur * 3.0).ceil() as u32;
+    let [r, g, b, a] = color;
+    let mut layer = RgbaImage::from_pixel(image.width() + margin * 2, image.height() + margin * 2, Rgba([r, g, b, 0]));
+    for (x, y, pixel) in image.enumerate_pixels() {
+        layer.put_pixel(x + margin, y + margin, Rgba([r, g, b, (pixel[3] as u32 * a as u32 / 255) as u8]));
+    }
+    if blur <= 0.0 {
+        return (layer, margin);
+    }
+
+    // A large gaussian is slow, blur a smaller copy and scale it back: the result is smooth anyway
+    let scale = (blur / 4.0).max(1.0);
+    let (width, height) = layer.dimensions();
+    let small = imageops::resize(&layer, ((width as f32 / scale) as u32).max(1), ((height as f32 / scale) as u32).max(1), FilterType::Triangle);
+    let blurred = imageops::blur(&small, blur / scale);
+    (imageops::resize(&blurred, width, height, FilterType::Triangle), margin)
+}
+
+/// Frames the image on the background described by the style.
+pub fn apply(image: &RgbaImage, style: &Style) -> Result<RgbaImage, String> {
+    let mut framed = image.clone();
+    round_corners(&mut framed, style.corner_radius);
+
+    let (width, height) = framed.dimensions();
+    let mut canvas_width = width + style.padding * 2;
+    let mut canvas_height = height + style.padding * 2;
+    // The aspect ratio only ever grows the canvas, the padding is a minimum
+    if let Some(ratio) = style.aspect_ratio.ratio() {
+        if (canvas_width as f32) / (canvas_height as f32) < ratio {
+            canvas_width = (canvas_height as f32 * ratio).round() as u32;
+        } else {
+            canvas_height = (canvas_width as f32 / ratio).round() as u32;
+        }
+    }
+
+    let mut canvas = background(style, canvas_width, canvas_height)?;
+    let left = (canvas_width - width) as i64 / 2;
+    let top = (canvas_height - height) as i64 / 2;
+
+    if style.shadow_color[3] > 0 {
+        let (shadow, margin) = shadow(&framed, style.shadow_color, style.shadow_blur);
+        imageops::overlay(&mut canvas, &shadow, left + style.shadow_offset[0] as i64 - margin as i64, top + style.shadow_offset[1] as i64 - margin as i64);
+    }
+    imageops::overlay(&mut canvas, &framed, left, top);
+
+    Ok(canvas)
+}
+
+/// Per-user configuration directory: %APPDATA% on Windows, $XDG_CONFIG_HOME or ~/.config elsewhere.
+fn config_dir() -> PathBuf {
+    let base = if cfg!(target_os = "windows") {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CONFIG_HOME").map(PathBuf::from).or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    base.unwrap_or_else(env::temp_dir).join("rust-screenshot")
+}
+
+pub fn load_presets() -> Vec<Preset> {
+    match fs::read_to_string(config_dir().join(PRESETS_FILE_NAME)) {
+        Ok(json) => serde_json::from_str(&json).unwrap_or_else(|error| {
+            println!("Error while reading the beautify presets -> {:?}", error);
+            Vec::new()
+        }),
+        Err(_) => vec![
+            Preset { name: String::from("Release notes"), style: Style::default() },
+            Preset {
+                name: String::from("Plain"),
+                style: Style {
+                    padding: 32,
+                    background: Background::Solid,
+                    color: [255, 255, 255, 255],
+                    corner_radius: 0,
+                    shadow_offset: [0, 4],
+                    shadow_blur: 8.0,
+                    shadow_color: [0, 0, 0, 60],
+                    ..Style::default()
+                },
+            },
+        ],
+    }
+}
+
+pub fn save_presets(presets: &[Preset]) -> Result<(), String> {
+    let directory = config_dir();
+    fs::create_dir_all(&directory).map_err(|e| format!("{:?}", e))?;
+    let json = serde_json::to_string_pretty(presets).map_err(|e| format!("{:?}", e))?;
+    fs::write(directory.join(PRESETS_FILE_NAME), json).map_err(|e| format!("{:?}", e))
+}
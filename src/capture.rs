@@ -0,0 +1,153 @@
+use image::{imageops::{self, FilterType::Triangle}, RgbaImage};
+use screenshots::Screen;
+
+/// Highest scale factor among the given screens: the stitched image uses this DPI so
+/// that no display loses detail.
+pub fn virtual_desktop_scale_factor(screens: &[Screen]) -> f32 {
+    screens
+        .iter()
+        .map(|s| s.display_info.scale_factor)
+        .fold(1.0, f32::max)
+}
+
+/// Bounding box (min_x, min_y, max_x, max_y) of all the screens, in logical coordinates.
+pub fn virtual_desktop_bounds(screens: &[Screen]) -> Option<(i32, i32, i32, i32)> {
+    if screens.is_empty() {
+        return None;
+    }
+
+    let min_x = screens.iter().map(|s| s.display_info.x).min().unwrap();
+    let min_y = screens.iter().map(|s| s.display_info.y).min().unwrap();
+    let max_x = screens.iter().map(|s| s.display_info.x + s.display_info.width as i32).max().unwrap();
+    let max_y = screens.iter().map(|s| s.display_info.y + s.display_info.height as i32).max().unwrap();
+
+    Some((min_x, min_y, max_x, max_y))
+}
+
+/// Captures every screen and places it on a single canvas according to its position
+/// on the virtual desktop. Gaps between displays are left transparent.
+pub fn capture_all_screens(screens: &[Screen]) -> Option<RgbaImage> {
+    let (min_x, min_y, max_x, max_y) = virtual_desktop_bounds(screens)?;
+    let scale_factor = virtual_desktop_scale_factor(screens);
+
+    let mut canvas = RgbaImage::new(
+        ((max_x - min_x) as f32 * scale_factor) as u32,
+        ((max_y - min_y) as f32 * scale_factor) as u32,
+    );
+
+    for screen in screens {
+        let info = &screen.display_info;
+        let image = match screen.capture() {
+            Ok(image) => image,
+            Err(error) => {
+                println!("Error while capturing {:?} -> {:?}", info, error);
+                continue;
+            }
+        };
+
+        // Screens with a lower scale factor are upscaled to the common DPI
+        let width = (info.width as f32 * scale_factor) as u32;
+        let height = (info.height as f32 * scale_factor) as u32;
+        let image = if image.width() != width || image.height() != height {
+            imageops::resize(&image, width, height, Triangle)
+        } else {
+            image
+        };
+
+        imageops::replace(
+            &mut canvas,
+            &image,
+            ((info.x - min_x) as f32 * scale_factor) as i64,
+            ((info.y - min_y) as f32 * scale_factor) as i64,
+        );
+    }
+
+    Some(canvas)
+}
+
+/// Captures only the given area of the virtual desktop (logical coordinates). Each screen
+/// overlapped by the area captures its own part via `capture_area`, so a region can span
+/// several displays.
+pub fn capture_region(screens: &[Screen], x: i32, y: i32, width: u32, height: u32) -> Option<RgbaImage> {
+    let overlapping: Vec<&Screen> = screens
+        .iter()
+        .filter(|s| {
+            let info = &s.display_info;
+            x < info.x + info.width as i32 && x + width as i32 > info.x
+                && y < info.y + info.height as i32 && y + height as i32 > info.y
+        })
+        .collect();
+
+    if overlapping.is_empty() {
+        return None;
+    }
+
+    let scale_factor = overlapping
+        .iter()
+        .map(|s| s.display_info.scale_factor)
+        .fold(1.0, f32::max);
+
+    let mut canvas = RgbaImage::new(
+        (width as f32 * scale_factor) as u32,
+        (height as f32 * scale_factor) as u32,
+    );
+
+    for screen in overlapping {
+        let info = &screen.display_info;
+        let min_x = x.max(info.x);
+        let min_y = y.max(info.y);
+        let max_x = (x + width as i32).min(info.x + info.width as i32);
+        let max_y = (y + height as i32).min(info.y + info.height as i32);
+
+        // capture_area wants coordinates relative to the screen
+        let image = match screen.capture_area(min_x - info.x, min_y - info.y, (max_x - min_x) as u32, (max_y - min_y) as u32) {
+            Ok(image) => image,
+            Err(error) => {
+                println!("Error while capturing area of {:?} -> {:?}", info, error);
+                continue;
+            }
+        };
+
+        let part_width = ((max_x - min_x) as f32 * scale_factor) as u32;
+        let part_height = ((max_y - min_y) as f32 * scale_factor) as u32;
+        let image = if image.width() != part_width || image.height() != part_height {
+            imageops::resize(&image, part_width, part_height, Triangle)
+        } else {
+            image
+        };
+
+        imageops::replace(
+            &mut canvas,
+            &image,
+            ((min_x - x) as f32 * scale_factor) as i64,
+            ((min_y - y) as f32 * scale_factor) as i64,
+        );
+    }
+
+    Some(canvas)
+}
+
+/// What a repeated capture (interval, recording) grabs at every shot.
+#[derive(Debug, Clone)]
+pub enum CaptureTarget {
+    Screen(Screen),
+    AllScreens(Vec<Screen>),
+    /// Screens plus a region in virtual desktop coordinates (x, y, width, height)
+    Region(Vec<Screen>, i32, i32, u32, u32),
+}
+
+impl CaptureTarget {
+    pub fn capture(&self) -> Option<RgbaImage> {
+        match self {
+            CaptureTarget::Screen(screen) => match screen.capture() {
+                Ok(image) => Some(image),
+                Err(error) => {
+                    println!("Error while capturing {:?} -> {:?}", screen, error);
+                    None
+                }
+            },
+            CaptureTarget::AllScreens(screens) => capture_all_screens(screens),
+            CaptureTarget::Region(screens, x, y, width, height) => capture_region(screens, *x, *y, *width, *height),
+        }
+    }
+}
@@ -0,0 +1,414 @@
+use std::{borrow::Cow, path::Path};
+use arboard::{Clipboard, ImageData};
+use base64::Engine;
+use image::RgbaImage;
+
+use crate::upload::encode_image;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CopyFormat {
+    Image,
+    /// The image, HTML embedding it as a data URI and the file path as text, all at once
+    EmbeddedHtml,
+    FilePath,
+    DataUri,
+    Html,
+    Markdown,
+}
+
+impl CopyFormat {
+    pub const ALL: [CopyFormat; 6] = [
+        CopyFormat::Image,
+        CopyFormat::EmbeddedHtml,
+        CopyFormat::FilePath,
+        CopyFormat::DataUri,
+        CopyFormat::Html,
+        CopyFormat::Markdown,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CopyFormat::Image => "Image",
+            CopyFormat::EmbeddedHtml => "HTML with embedded image",
+            CopyFormat::FilePath => "Saved file path",
+            CopyFormat::DataUri => "Data URI (base64 PNG)",
+            CopyFormat::Html => "HTML <img> snippet",
+            CopyFormat::Markdown => "Markdown image link",
+        }
+    }
+
+    /// Formats that point at the saved file instead of carrying the pixels
+    pub fn needs_saved_file(&self) -> bool {
+        matches!(self, CopyFormat::FilePath | CopyFormat::Html | CopyFormat::Markdown | CopyFormat::EmbeddedHtml)
+    }
+}
+
+pub fn data_uri(image: &RgbaImage) -> Result<String, String> {
+    let (data, _) = encode_image(image, ".png")?;
+    Ok(png_data_uri(&data))
+}
+
+fn png_data_uri(png: &[u8]) -> String {
+    format!("data:image/png;base64,{}", base64::engine::general_purpose::STANDARD.encode(png))
+}
+
+pub fn file_url(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let path = path.replace(' ', "%20");
+    if path.starts_with('/') {
+        format!("file://{}", path)
+    } else {
+        format!("file:///{}", path)
+    }
+}
+
+fn file_name(path: &str) -> String {
+    Path::new(path).file_name().map(|f| f.to_string_lossy().to_string()).unwrap_or_default()
+}
+
+pub fn html_img(src: &str, alt: &str) -> String {
+    format!("<img src=\"{}\" alt=\"{}\">", src.replace('"', "&quot;"), alt.replace('"', "&quot;"))
+}
+
+pub fn markdown_image(path: &str) -> String {
+    format!("![{}](<{}>)", file_name(path), path)
+}
+
+/// Puts the image on the clipboard in the given format. `saved_path` is required by the
+/// formats that reference the file.
+pub fn copy(image: &RgbaImage, saved_path: Option<&str>, format: CopyFormat) -> Result<(), String> {
+    let mut clipboard = Clipboard::new().map_err(|e| format!("{:?}", e))?;
+    let saved_path = match (format.needs_saved_file(), saved_path) {
+        (true, None) => return Err(String::from("The screenshot has to be saved first")),
+        (_, path) => path.unwrap_or_default(),
+    };
+
+    let result = match format {
+        CopyFormat::Image => clipboard.set_image(ImageData {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            bytes: Cow::from(image.as_raw()),
+        }),
+        CopyFormat::EmbeddedHtml => {
+            // arboard replaces the whole clipboard on every write, the formats go in together natively
+            let (png, _) = encode_image(image, ".png")?;
+            let html = html_img(&png_data_uri(&png), &file_name(saved_path));
+            return platform::set_all(image, &png, &html, saved_path);
+        },
+        CopyFormat::FilePath => clipboard.set_text(saved_path),
+        CopyFormat::DataUri => clipboard.set_text(data_uri(image)?),
+        CopyFormat::Html => clipboard.set_text(html_img(&file_url(saved_path), &file_name(saved_path))),
+        CopyFormat::Markdown => clipboard.set_text(markdown_image(saved_path)),
+    };
+    result.map_err(|e| format!("{:?}", e))
+}
+
+/// The image as a bottom-up 32-bit DIB, what CF_DIB holds on Windows.
+#[cfg(any(target_os = "windows", test))]
+fn dib(image: &RgbaImage) -> Vec<u8> {
+    let (width, height) = image.dimensions();
+    let mut data = Vec::with_capacity(40 + image.as_raw().len());
+    // BITMAPINFOHEADER, a positive height means the rows are stored bottom-up
+    data.extend_from_slice(&40u32.to_le_bytes());
+    data.extend_from_slice(&(width as i32).to_le_bytes());
+    data.extend_from_slice(&(height as i32).to_le_bytes());
+    data.extend_from_slice(&1u16.to_le_bytes());
+    data.extend_from_slice(&32u16.to_le_bytes());
+    data.extend_from_slice(&0u32.to_le_bytes());
+    data.extend_from_slice(&(width * height * 4).to_le_bytes());
+    data.extend_from_slice(&[0; 16]);
+    for row in image.rows().rev() {
+        for pixel in row {
+            data.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+        }
+    }
+    data
+}
+
+/// Wraps an HTML fragment in the "HTML Format" header, which gives the byte offsets of the
+/// document and of the fragment.
+#[cfg(any(target_os = "windows", test))]
+fn cf_html(fragment: &str) -> String {
+    let header = |start_html: usize, end_html: usize, start_fragment: usize, end_fragment: usize| format!(
+        "Version:0.9\r\nStartHTML:{:010}\r\nEndHTML:{:010}\r\nStartFragment:{:010}\r\nEndFragment:{:010}\r\n",
+        start_html, end_html, start_fragment, end_fragment
+    );
+    let (prefix, suffix) = ("<html><body>\r\n<!--StartFragment-->", "<!--EndFragment-->\r\n</body></html>");
+    let start_html = header(0, 0, 0, 0).len();
+    let start_fragment = start_html + prefix.len();
+    let end_fragment = start_fragment + fragment.len();
+    let end_html = end_fragment + suffix.len();
+    format!("{}{}{}{}", header(start_html, end_html, start_fragment, end_fragment), prefix, fragment, suffix)
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::{os::raw::c_void, ptr, thread, time::Duration};
+    use image::RgbaImage;
+
+    const CF_DIB: u32 = 8;
+    const CF_UNICODETEXT: u32 = 13;
+    const GMEM_MOVEABLE: u32 = 0x2;
+
+    #[link(name = "user32")]
+    extern "system" {
+        fn OpenClipboard(owner: *mut c_void) -> i32;
+        fn EmptyClipboard() -> i32;
+        fn CloseClipboard() -> i32;
+        fn RegisterClipboardFormatW(name: *const u16) -> u32;
+        fn SetClipboardData(format: u32, data: *mut c_void) -> *mut c_void;
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GlobalAlloc(flags: u32, bytes: usize) -> *mut c_void;
+        fn GlobalLock(memory: *mut c_void) -> *mut c_void;
+        fn GlobalUnlock(memory: *mut c_void) -> i32;
+        fn GlobalFree(memory: *mut c_void) -> *mut c_void;
+    }
+
+    fn register(name: &str) -> u32 {
+        let name: Vec<u16> = name.encode_utf16().chain([0]).collect();
+        unsafe { RegisterClipboardFormatW(name.as_ptr()) }
+    }
+
+    /// The clipboard owns the memory once SetClipboardData succeeds.
+    unsafe fn set_data(format: u32, data: &[u8]) -> Result<(), String> {
+        let memory = GlobalAlloc(GMEM_MOVEABLE, data.len());
+        if memory.is_null() {
+            return Err(String::from("GlobalAlloc failed"));
+        }
+        ptr::copy_nonoverlapping(data.as_ptr(), GlobalLock(memory) as *mut u8, data.len());
+        GlobalUnlock(memory);
+        if SetClipboardData(format, memory).is_null() {
+            GlobalFree(memory);
+            return Err(format!("SetClipboardData failed for the clipboard format {}", format));
+        }
+        Ok(())
+    }
+
+    pub fn set_all(image: &RgbaImage, png: &[u8], html: &str, text: &str) -> Result<(), String> {
+        // Clipboard managers keep the clipboard open for a moment after every change
+        let mut attempts = 0;
+        while unsafe { OpenClipboard(ptr::null_mut()) } == 0 {
+            attempts += 1;
+            if attempts == 10 {
+                return Err(String::from("The clipboard is used by another application"));
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        let text: Vec<u8> = text.encode_utf16().chain([0]).flat_map(|c| c.to_le_bytes()).collect();
+        let result = unsafe {
+            if EmptyClipboard() == 0 {
+                Err(String::from("EmptyClipboard failed"))
+            } else {
+                set_data(CF_DIB, &super::dib(image))
+                    .and_then(|_| set_data(register("PNG"), png))
+                    .and_then(|_| set_data(register("HTML Format"), super::cf_html(html).as_bytes()))
+                    .and_then(|_| set_data(CF_UNICODETEXT, &text))
+            }
+        };
+        unsafe { CloseClipboard() };
+        result
+    }
+}
+
+/// An X11 clipboard is served by its owner, a thread answers the paste requests until
+/// another application takes the clipboard over.
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::{sync::mpsc, thread};
+    use image::RgbaImage;
+    use x11rb::{
+        connection::{Connection, RequestConnection},
+        protocol::{xproto::{Atom, AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _, CreateWindowAux, EventMask, PropMode, Property, SelectionNotifyEvent, SelectionRequestEvent, Window, WindowClass, SELECTION_NOTIFY_EVENT}, Event},
+        rust_connection::RustConnection,
+        wrapper::ConnectionExt as _,
+        COPY_DEPTH_FROM_PARENT, CURRENT_TIME, NONE,
+    };
+
+    x11rb::atom_manager! {
+        Atoms: AtomsCookie {
+            CLIPBOARD,
+            TARGETS,
+            INCR,
+            UTF8_STRING,
+            PNG: b"image/png",
+            HTML: b"text/html",
+            TEXT: b"text/plain;charset=utf-8",
+        }
+    }
+
+    /// A value too large for one request, sent in chunks each time the requestor deletes the property
+    struct Transfer {
+        requestor: Window,
+        property: Atom,
+        target: Atom,
+        offset: usize,
+    }
+
+    struct Owner {
+        connection: RustConnection,
+        window: Window,
+        atoms: Atoms,
+        png: Vec<u8>,
+        html: Vec<u8>,
+        text: Vec<u8>,
+    }
+
+    impl Owner {
+        fn open(png: Vec<u8>, html: Vec<u8>, text: Vec<u8>) -> Result<Self, String> {
+            let (connection, screen) = x11rb::connect(None).map_err(|e| e.to_string())?;
+            let root = connection.setup().roots[screen].root;
+            let atoms = Atoms::new(&connection).map_err(|e| e.to_string())?.reply().map_err(|e| e.to_string())?;
+            let window = connection.generate_id().map_err(|e| e.to_string())?;
+            connection.create_window(COPY_DEPTH_FROM_PARENT, window, root, 0, 0, 1, 1, 0, WindowClass::INPUT_OUTPUT, 0, &CreateWindowAux::new())
+                .map_err(|e| e.to_string())?;
+            connection.set_selection_owner(window, atoms.CLIPBOARD, CURRENT_TIME).map_err(|e| e.to_string())?;
+            let owner = connection.get_selection_owner(atoms.CLIPBOARD).map_err(|e| e.to_string())?
+                .reply().map_err(|e| e.to_string())?
+                .owner;
+            if owner != window {
+                return Err(String::from("Could not take the clipboard over"));
+            }
+            Ok(Owner { connection, window, atoms, png, html, text })
+        }
+
+        /// Values above this size are sent incrementally
+        fn chunk_size(&self) -> usize {
+            self.connection.maximum_request_bytes() / 4
+        }
+
+        fn data(&self, target: Atom) -> Option<&[u8]> {
+            match target {
+                t if t == self.atoms.PNG => Some(&self.png),
+                t if t == self.atoms.HTML => Some(&self.html),
+                t if t == self.atoms.UTF8_STRING || t == self.atoms.TEXT => Some(&self.text),
+                _ => None,
+            }
+        }
+
+        fn answer(&self, request: &SelectionRequestEvent, transfers: &mut Vec<Transfer>) -> Result<(), String> {
+            let connection = &self.connection;
+            // Obsolete clients leave the property out and expect the target to be used
+            let mut property = if request.property == NONE { request.target } else { request.property };
+
+            if request.target == self.atoms.TARGETS {
+                let targets = [self.atoms.TARGETS, self.atoms.PNG, self.atoms.HTML, self.atoms.UTF8_STRING, self.atoms.TEXT];
+                connection.change_property32(PropMode::REPLACE, request.requestor, property, AtomEnum::ATOM, &targets).map_err(|e| e.to_string())?;
+            } else if let Some(data) = self.data(request.target) {
+                if data.len() > self.chunk_size() {
+                    connection.change_window_attributes(request.requestor, &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE))
+                        .map_err(|e| e.to_string())?;
+                    connection.change_property32(PropMode::REPLACE, request.requestor, property, self.atoms.INCR, &[data.len() as u32])
+                        .map_err(|e| e.to_string())?;
+                    transfers.push(Transfer { requestor: request.requestor, property, target: request.target, offset: 0 });
+                } else {
+                    connection.change_property8(PropMode::REPLACE, request.requestor, property, request.target, data).map_err(|e| e.to_string())?;
+                }
+            } else {
+                property = NONE;
+            }
+
+            let notify = SelectionNotifyEvent {
+                response_type: SELECTION_NOTIFY_EVENT,
+                sequence: 0,
+                time: request.time,
+                requestor: request.requestor,
+                selection: request.selection,
+                target: request.target,
+                property,
+            };
+            connection.send_event(false, request.requestor, EventMask::NO_EVENT, notify).map_err(|e| e.to_string())?;
+            connection.flush().map_err(|e| e.to_string())
+        }
+
+        fn serve(&self) -> Result<(), String> {
+            let mut transfers: Vec<Transfer> = Vec::new();
+            loop {
+                match self.connection.wait_for_event().map_err(|e| e.to_string())? {
+                    Event::SelectionClear(event) if event.selection == self.atoms.CLIPBOARD && event.owner == self.window => return Ok(()),
+                    Event::SelectionRequest(request) => self.answer(&request, &mut transfers)?,
+                    Event::PropertyNotify(event) if event.state == Property::DELETE => {
+                        let Some(index) = transfers.iter().position(|t| t.requestor == event.window && t.property == event.atom) else { continue };
+                        let transfer = &mut transfers[index];
+                        let data = self.data(transfer.target).unwrap_or_default();
+                        let end = (transfer.offset + self.chunk_size()).min(data.len());
+                        // An empty chunk tells the requestor that the transfer is complete
+                        let done = transfer.offset == data.len();
+                        self.connection.change_property8(PropMode::REPLACE, transfer.requestor, transfer.property, transfer.target, &data[transfer.offset..end])
+                            .map_err(|e| e.to_string())?;
+                        self.connection.flush().map_err(|e| e.to_string())?;
+                        transfer.offset = end;
+                        if done {
+                            transfers.remove(index);
+                        }
+                    },
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    pub fn set_all(_image: &RgbaImage, png: &[u8], html: &str, text: &str) -> Result<(), String> {
+        let (sender, receiver) = mpsc::channel();
+        let (png, html, text) = (png.to_vec(), html.as_bytes().to_vec(), text.as_bytes().to_vec());
+        thread::spawn(move || {
+            match Owner::open(png, html, text) {
+                Ok(owner) => {
+                    let _ = sender.send(Ok(()));
+                    if let Err(error) = owner.serve() {
+                        println!("Stopped serving the clipboard -> {}", error);
+                    }
+                },
+                Err(error) => {
+                    let _ = sender.send(Err(error));
+                },
+            }
+        });
+        receiver.recv().unwrap_or_else(|_| Err(String::from("The clipboard thread stopped")))
+    }
+}
+
+/// Without native multi-format support the HTML goes on the clipboard with the path as its text.
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+mod platform {
+    use arboard::Clipboard;
+    use image::RgbaImage;
+
+    pub fn set_all(_image: &RgbaImage, _png: &[u8], html: &str, text: &str) -> Result<(), String> {
+        let mut clipboard = Clipboard::new().map_err(|e| format!("{:?}", e))?;
+        clipboard.set_html(html, Some(text)).map_err(|e| format!("{:?}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn dib_is_bottom_up_bgra() {
+        let image = RgbaImage::from_fn(2, 2, |x, y| Rgba([x as u8, y as u8, 10, 200]));
+        let dib = dib(&image);
+        assert_eq!(dib.len(), 40 + 16);
+        assert_eq!(&dib[4..12], &[2, 0, 0, 0, 2, 0, 0, 0]);
+        assert_eq!(&dib[14..16], &[32, 0]);
+        // The first row stored is the bottom one, y = 1
+        assert_eq!(&dib[40..48], &[10, 1, 0, 200, 10, 1, 1, 200]);
+        assert_eq!(&dib[48..56], &[10, 0, 0, 200, 10, 0, 1, 200]);
+    }
+
+    #[test]
+    fn cf_html_offsets() {
+        let fragment = "<img src=\"data:image/png;base64,AAAA\" alt=\"été.png\">";
+        let html = cf_html(fragment);
+        let offset = |key: &str| -> usize {
+            let start = html.find(key).unwrap() + key.len() + 1;
+            html[start..start + 10].parse().unwrap()
+        };
+        assert_eq!(&html[offset("StartFragment")..offset("EndFragment")], fragment);
+        assert!(html[offset("StartHTML")..].starts_with("<html>"));
+        assert_eq!(offset("EndHTML"), html.len());
+    }
+}
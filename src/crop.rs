@@ -0,0 +1,253 @@
+use image::{imageops, RgbaImage};
+
+/// Image pixels shown around the cursor by the loupe, odd so that one is in the middle
+const LOUPE_PIXELS: usize = 15;
+/// Size of every image pixel inside the loupe
+const LOUPE_ZOOM: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AspectRatio {
+    Free,
+    Square,
+    Standard,
+    Widescreen,
+    Custom,
+}
+
+impl AspectRatio {
+    pub const ALL: [AspectRatio; 5] = [
+        AspectRatio::Free,
+        AspectRatio::Square,
+        AspectRatio::Standard,
+        AspectRatio::Widescreen,
+        AspectRatio::Custom,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AspectRatio::Free => "Free",
+            AspectRatio::Square => "1:1",
+            AspectRatio::Standard => "4:3",
+            AspectRatio::Widescreen => "16:9",
+            AspectRatio::Custom => "Custom",
+        }
+    }
+}
+
+/// Twitter summary card with a large image, also the beautify aspect ratio of the same name
+pub const TWITTER_CARD: (u32, u32) = (1200, 628);
+
+/// Common sizes for fixed-size selections, in pixels
+pub const SIZE_PRESETS: [(&str, u32, u32); 6] = [
+    ("Full HD", 1920, 1080),
+    ("HD", 1280, 720),
+    ("Open Graph", 1200, 630),
+    ("Twitter card", TWITTER_CARD.0, TWITTER_CARD.1),
+    ("Instagram", 1080, 1080),
+    ("Avatar", 400, 400),
+];
+
+#[derive(Debug, Clone)]
+pub struct CropSettings {
+    pub aspect_ratio: AspectRatio,
+    /// Width and height of the custom aspect ratio
+    pub custom_ratio: [u32; 2],
+    /// A click places a selection of exactly this size
+    pub fixed_size: Option<[u32; 2]>,
+    /// Magnified pixels around the cursor
+    pub loupe: bool,
+}
+
+impl Default for CropSettings {
+    fn default() -> Self {
+        CropSettings {
+            aspect_ratio: AspectRatio::Free,
+            custom_ratio: [3, 2],
+            fixed_size: None,
+            loupe: true,
+        }
+    }
+}
+
+impl CropSettings {
+    /// Width divided by height, None when free.
+    pub fn ratio(&self) -> Option<f32> {
+        match self.aspect_ratio {
+            AspectRatio::Free => None,
+            AspectRatio::Square => Some(1.0),
+            AspectRatio::Standard => Some(4.0 / 3.0),
+            AspectRatio::Widescreen => Some(16.0 / 9.0),
+            AspectRatio::Custom => Some(self.custom_ratio[0].max(1) as f32 / self.custom_ratio[1].max(1) as f32),
+        }
+    }
+}
+
+/// Which part of the selection the arrow keys move.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Nudge {
+    Move,
+    TopLeft,
+    BottomRight,
+}
+
+/// Crop rectangle, in pixels of the image being cropped.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Selection {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Selection {
+    /// Rectangle dragged from `start` to `end`, following the aspect ratio or the fixed size
+    /// of the settings, inside an image of `bounds` pixels.
+    pub fn from_drag(start: (f32, f32), end: (f32, f32), settings: &CropSettings, bounds: (u32, u32)) -> Selection {
+        let (max_x, max_y) = (bounds.0 as f32, bounds.1 as f32);
+
+        // A fixed size selection is centered on the cursor
+        if let Some([width, height]) = settings.fixed_size {
+            let (width, height) = (width.min(bounds.0), height.min(bounds.1));
+            let x = (end.0 - width as f32 / 2.0).clamp(0.0, max_x - width as f32);
+            let y = (end.1 - height as f32 / 2.0).clamp(0.0, max_y - height as f32);
+            return Selection { x: x.round() as u32, y: y.round() as u32, width, height };
+        }
+
+        let mut dx = end.0 - start.0;
+        let mut dy = end.1 - start.1;
+        // Room from the start point towards the drag direction
+        let room_x = if dx < 0.0 { start.0 } else { max_x - start.0 };
+        let room_y = if dy < 0.0 { start.1 } else { max_y - start.1 };
+
+        match settings.ratio() {
+            Some(ratio) => {
+                // The longer side of the drag decides, the other one follows
+                if dx.abs() / ratio > dy.abs() {
+                    dy = dx.abs() / ratio * dy.signum();
+                } else {
+                    dx = dy.abs() * ratio * dx.signum();
+                }
+                // Shrink both sides to stay inside the image
+                let shrink = (room_x / dx.abs().max(f32::EPSILON)).min(room_y / dy.abs().max(f32::EPSILON)).min(1.0);
+                dx *= shrink;
+                dy *= shrink;
+            },
+            None => {
+                dx = dx.clamp(-room_x, room_x);
+                dy = dy.clamp(-room_y, room_y);
+            }
+        }
+
+        let x = start.0.min(start.0 + dx).max(0.0);
+        let y = start.1.min(start.1 + dy).max(0.0);
+        Selection { x: x.round() as u32, y: y.round() as u32, width: dx.abs().round() as u32, height: dy.abs().round() as u32 }
+    }
+
+    /// The same selection moved and shrunk to fit an image of `bounds` pixels, at least 1x1.
+    pub fn clamp(&self, bounds: (u32, u32)) -> Selection {
+        let x = self.x.min(bounds.0.saturating_sub(1));
+        let y = self.y.min(bounds.1.saturating_sub(1));
+        Selection { x, y, width: self.width.clamp(1, bounds.0 - x), height: self.height.clamp(1, bounds.1 - y) }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// Moves the selection, or only its top left or bottom right corner, by `dx` and `dy` pixels.
+    pub fn nudge(&mut self, dx: i32, dy: i32, nudge: Nudge, bounds: (u32, u32)) {
+        // A click without a drag leaves an empty selection, there is nothing to move
+        if self.is_empty() {
+            return;
+        }
+        *self = self.clamp(bounds);
+        let (left, top) = (self.x as i64, self.y as i64);
+        let (right, bottom) = (left + self.width as i64, top + self.height as i64);
+        let (max_x, max_y) = (bounds.0 as i64, bounds.1 as i64);
+        let (dx, dy) = (dx as i64, dy as i64);
+
+        let (left, top, right, bottom) = match nudge {
+            Nudge::Move => {
+                let dx = dx.clamp(-left, max_x - right);
+                let dy = dy.clamp(-top, max_y - bottom);
+                (left + dx, top + dy, right + dx, bottom + dy)
+            },
+            Nudge::TopLeft => ((left + dx).clamp(0, right - 1), (top + dy).clamp(0, bottom - 1), right, bottom),
+            Nudge::BottomRight => (left, top, (right + dx).clamp(left + 1, max_x), (bottom + dy).clamp(top + 1, max_y)),
+        };
+        *self = Selection { x: left as u32, y: top as u32, width: (right - left) as u32, height: (bottom - top) as u32 };
+    }
+}
+
+pub fn crop(image: &RgbaImage, selection: Selection) -> RgbaImage {
+    let selection = selection.clamp(image.dimensions());
+    imageops::crop_imm(image, selection.x, selection.y, selection.width, selection.height).to_image()
+}
+
+/// Draws a magnified view of the image pixels around `center` in the window buffer, next to
+/// the cursor and on the side where it fits.
+pub fn draw_loupe(buffer: &mut [u32], width: usize, height: usize, image: &RgbaImage, center: (u32, u32), cursor: (usize, usize)) {
+    let size = LOUPE_PIXELS * LOUPE_ZOOM;
+    if width < size + 2 || height < size + 2 {
+        return;
+    }
+    let left = if cursor.0 + 20 + size + 2 < width { cursor.0 + 20 } else { cursor.0.saturating_sub(20 + size + 2) };
+    let top = if cursor.1 + 20 + size + 2 < height { cursor.1 + 20 } else { cursor.1.saturating_sub(20 + size + 2) };
+    let half = (LOUPE_PIXELS / 2) as i64;
+
+    for j in 0..size + 2 {
+        for i in 0..size + 2 {
+            // One pixel white border around the loupe
+            let color = if i == 0 || j == 0 || i == size + 1 || j == size + 1 {
+                0xFFFFFF
+            } else {
+                let (cell_x, cell_y) = ((i - 1) / LOUPE_ZOOM, (j - 1) / LOUPE_ZOOM);
+                let x = center.0 as i64 + cell_x as i64 - half;
+                let y = center.1 as i64 + cell_y as i64 - half;
+                let on_cell_edge = (i - 1) % LOUPE_ZOOM == 0 || (j - 1) % LOUPE_ZOOM == 0 || i % LOUPE_ZOOM == 0 || j % LOUPE_ZOOM == 0;
+                if cell_x as i64 == half && cell_y as i64 == half && on_cell_edge {
+                    // Outline of the pixel under the cursor
+                    0xFF0000
+                } else if x < 0 || y < 0 || x >= image.width() as i64 || y >= image.height() as i64 {
+                    0x202020
+                } else {
+                    let p = image.get_pixel(x as u32, y as u32);
+                    ((p[0] as u32) << 16) | ((p[1] as u32) << 8) | p[2] as u32
+                }
+            };
+            buffer[(top + j) * width + left + i] = color;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nudge_stays_inside_the_image() {
+        let bounds = (100, 50);
+        let mut selection = Selection { x: 0, y: 0, width: 0, height: 0 };
+        selection.nudge(-1, -1, Nudge::TopLeft, bounds);
+        assert_eq!(selection, Selection { x: 0, y: 0, width: 0, height: 0 });
+
+        let mut selection = Selection { x: 0, y: 0, width: 10, height: 10 };
+        selection.nudge(-5, -5, Nudge::Move, bounds);
+        assert_eq!(selection, Selection { x: 0, y: 0, width: 10, height: 10 });
+        selection.nudge(5, 5, Nudge::TopLeft, bounds);
+        assert_eq!(selection, Selection { x: 5, y: 5, width: 5, height: 5 });
+        selection.nudge(20, 20, Nudge::TopLeft, bounds);
+        assert_eq!(selection, Selection { x: 9, y: 9, width: 1, height: 1 });
+
+        let mut selection = Selection { x: 99, y: 49, width: 1, height: 1 };
+        selection.nudge(5, 5, Nudge::BottomRight, bounds);
+        assert_eq!(selection, Selection { x: 99, y: 49, width: 1, height: 1 });
+        selection.nudge(5, 5, Nudge::Move, bounds);
+        assert_eq!(selection, Selection { x: 99, y: 49, width: 1, height: 1 });
+
+        // Larger than the image, from before a resize
+        let mut selection = Selection { x: 20, y: 10, width: 200, height: 100 };
+        selection.nudge(-1, 0, Nudge::Move, bounds);
+        assert_eq!(selection, Selection { x: 19, y: 10, width: 80, height: 40 });
+    }
+}
@@ -0,0 +1,361 @@
+use image::{Rgba, RgbaImage};
+
+/// Pixels whose channels differ by at most this much are considered equal, it hides
+/// compression noise and subpixel antialiasing.
+pub const DEFAULT_TOLERANCE: u8 = 16;
+
+/// Changed pixels are grouped in cells of this size before looking for regions, so that
+/// nearby changes end up in the same bounding box.
+const CELL_SIZE: u32 = 8;
+
+pub struct DiffResult {
+    /// Offset of the second image relative to the first, after alignment
+    pub offset: (i32, i32),
+    pub changed_pixels: u64,
+    /// Percentage of changed pixels in the area covered by both images
+    pub score: f64,
+    /// x, y, width, height in the coordinates of the first image
+    pub regions: Vec<(u32, u32, u32, u32)>,
+    /// Changed pixels, one bool per pixel of the first image
+    pub mask: Vec<bool>,
+}
+
+fn gray(image: &RgbaImage) -> Vec<f32> {
+    image.pixels().map(|p| 0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32).collect()
+}
+
+/// Mean absolute difference of the overlap when `b` is moved by (dx, dy).
+fn mismatch(a: &[f32], a_size: (u32, u32), b: &[f32], b_size: (u32, u32), dx: i32, dy: i32) -> f32 {
+    let (aw, ah) = (a_size.0 as i32, a_size.1 as i32);
+    let (bw, bh) = (b_size.0 as i32, b_size.1 as i32);
+    let (x0, x1) = (dx.max(0), (bw + dx).min(aw));
+    let (y0, y1) = (dy.max(0), (bh + dy).min(ah));
+    // Require a reasonable overlap, otherwise tiny overlaps win by chance
+    if (x1 - x0) < aw / 2 || (y1 - y0) < ah / 2 {
+        return f32::MAX;
+    }
+
+    let mut total = 0.0;
+    let mut count = 0;
+    for y in y0..y1 {
+        for x in x0..x1 {
+            total += (a[(y * aw + x) as usize] - b[((y - dy) * bw + (x - dx)) as usize]).abs();
+            count += 1;
+        }
+    }
+    if count == 0 { f32::MAX } else { total / count as f32 }
+}
+
+/// Averages every 2x2 block of a gray image.
+fn halve(gray: &[f32], size: (u32, u32)) -> (Vec<f32>, (u32, u32)) {
+    let (width, height) = (size.0 as usize, size.1 as usize);
+    let (half_width, half_height) = ((width / 2).max(1), (height / 2).max(1));
+    let mut half = Vec::with_capacity(half_width * half_height);
+    for y in 0..half_height {
+        let (y0, y1) = ((y * 2).min(height - 1), (y * 2 + 1).min(height - 1));
+        for x in 0..half_width {
+            let (x0, x1) = ((x * 2).min(width - 1), (x * 2 + 1).min(width - 1));
+            half.push((gray[y0 * width + x0] + gray[y0 * width + x1] + gray[y1 * width + x0] + gray[y1 * width + x1]) / 4.0);
+        }
+    }
+    (half, (half_width as u32, half_height as u32))
+}
+
+/// The offset among `candidates` with the smallest mismatch, the smallest shift when scores are equal.
+fn best_offset(a: &(Vec<f32>, (u32, u32)), b: &(Vec<f32>, (u32, u32)), candidates: impl Iterator<Item = (i32, i32)>) -> (i32, i32) {
+    let mut best: (i32, i32, f32) = (0, 0, f32::MAX);
+    for (dx, dy) in candidates {
+        let score = mismatch(&a.0, a.1, &b.0, b.1, dx, dy);
+        if score < best.2 || (score == best.2 && dx.abs() + dy.abs() < best.0.abs() + best.1.abs()) {
+            best = (dx, dy, score);
+        }
+    }
+    (best.0, best.1)
+}
+
+/// Finds the translation of `b` that best matches `a`, up to `max_shift` pixels in each
+/// direction. The whole range is searched on copies halved down to about 256 pixels, then
+/// the match is refined by one pixel around it at every level up to the full resolution,
+/// so large captures cost a handful of full size comparisons.
+pub fn align(a: &RgbaImage, b: &RgbaImage, max_shift: u32) -> (i32, i32) {
+    let mut levels = vec![((gray(a), a.dimensions()), (gray(b), b.dimensions()))];
+    while let Some((small_a, small_b)) = levels.last().filter(|(small_a, _)| small_a.1.0.max(small_a.1.1) > 256) {
+        let level = (halve(&small_a.0, small_a.1), halve(&small_b.0, small_b.1));
+        levels.push(level);
+    }
+
+    let (small_a, small_b) = levels.pop().unwrap();
+    let search = (max_shift >> levels.len()) as i32;
+    let mut best = best_offset(&small_a, &small_b, (-search..=search).flat_map(|dy| (-search..=search).map(move |dx| (dx, dy))));
+
+    let max_shift = max_shift as i32;
+    for (level_a, level_b) in levels.iter().rev() {
+        let (cx, cy) = (best.0 * 2, best.1 * 2);
+        let around = (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (cx + dx, cy + dy)));
+        best = best_offset(level_a, level_b, around.filter(|(dx, dy)| dx.abs() <= max_shift && dy.abs() <= max_shift));
+    }
+    best
+}
+
+/// Bounding boxes of the groups of changed cells (8-connected).
+fn regions(mask: &[bool], width: u32, height: u32) -> Vec<(u32, u32, u32, u32)> {
+    let columns = width.div_ceil(CELL_SIZE);
+    let rows = height.div_ceil(CELL_SIZE);
+    let mut cells = vec![false; (columns * rows) as usize];
+    for y in 0..height {
+        for x in 0..width {
+            if mask[(y * width + x) as usize] {
+                cells[((y / CELL_SIZE) * columns + x / CELL_SIZE) as usize] = true;
+            }
+        }
+    }
+
+    let mut regions = Vec::new();
+    let mut visited = vec![false; cells.len()];
+    for start in 0..cells.len() {
+        if !cells[start] || visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        let mut stack = vec![start];
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (u32::MAX, u32::MAX, 0, 0);
+        while let Some(cell) = stack.pop() {
+            let (cx, cy) = (cell as u32 % columns, cell as u32 / columns);
+            min_x = min_x.min(cx);
+            min_y = min_y.min(cy);
+            max_x = max_x.max(cx);
+            max_y = max_y.max(cy);
+            for ny in cy.saturating_sub(1)..=(cy + 1).min(rows - 1) {
+                for nx in cx.saturating_sub(1)..=(cx + 1).min(columns - 1) {
+                    let neighbour = (ny * columns + nx) as usize;
+                    if cells[neighbour] && !visited[neighbour] {
+                        visited[neighbour] = true;
+                        stack.push(neighbour);
+                    }
+                }
+            }
+        }
+
+        let x = min_x * CELL_SIZE;
+        let y = min_y * CELL_SIZE;
+        let right = ((max_x + 1) * CELL_SIZE).min(width);
+        let bottom = ((max_y + 1) * CELL_SIZE).min(height);
+        regions.push((x, y, right - x, bottom - y));
+    }
+    regions
+}
+
+/// Compares `b`, moved by `offset`, with `a`. Only the area covered by both images is compared,
+/// the borders left uncovered by the alignment are not changes.
+pub fn compare(a: &RgbaImage, b: &RgbaImage, offset: (i32, i32), tolerance: u8) -> DiffResult {
+    let (width, height) = a.dimensions();
+    let mut mask = vec![false; (width * height) as usize];
+    let mut changed_pixels = 0;
+    let mut compared_pixels: u64 = 0;
+    for (x, y, pa) in a.enumerate_pixels() {
+        let bx = x as i32 - offset.0;
+        let by = y as i32 - offset.1;
+        if bx < 0 || by < 0 || bx >= b.width() as i32 || by >= b.height() as i32 {
+            continue;
+        }
+        compared_pixels += 1;
+        let pb = b.get_pixel(bx as u32, by as u32);
+        if (0..4).any(|c| pa[c].abs_diff(pb[c]) > tolerance) {
+            mask[(y * width + x) as usize] = true;
+            changed_pixels += 1;
+        }
+    }
+
+    let total = compared_pixels.max(1);
+    DiffResult {
+        offset,
+        changed_pixels,
+        score: changed_pixels as f64 * 100.0 / total as f64,
+        regions: regions(&mask, width, height),
+        mask,
+    }
+}
+
+/// The first image faded to gray, changed pixels in red and the regions outlined.
+pub fn highlight(a: &RgbaImage, result: &DiffResult) -> RgbaImage {
+    let mut image = RgbaImage::from_fn(a.width(), a.height(), |x, y| {
+        if result.mask[(y * a.width() + x) as usize] {
+            Rgba([255, 0, 0, 255])
+        } else {
+            let p = a.get_pixel(x, y);
+            let gray = (0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32) as u8;
+            let faded = 128 + gray / 2;
+            Rgba([faded, faded, faded, 255])
+        }
+    });
+
+    for &(x, y, w, h) in &result.regions {
+        let outline = Rgba([255, 160, 0, 255]);
+        for i in x..x + w {
+            image.put_pixel(i, y, outline);
+            image.put_pixel(i, y + h - 1, outline);
+        }
+        for j in y..y + h {
+            image.put_pixel(x, j, outline);
+            image.put_pixel(x + w - 1, j, outline);
+        }
+    }
+    image
+}
+
+/// `b` moved by `offset` and blended over `a`, `amount` 0 shows only `a`, 1 only `b`.
+pub fn onion_skin(a: &RgbaImage, b: &RgbaImage, offset: (i32, i32), amount: f32) -> RgbaImage {
+    let amount = amount.clamp(0.0, 1.0);
+    RgbaImage::from_fn(a.width(), a.height(), |x, y| {
+        let pa = a.get_pixel(x, y);
+        let bx = x as i32 - offset.0;
+        let by = y as i32 - offset.1;
+        if bx < 0 || by < 0 || bx >= b.width() as i32 || by >= b.height() as i32 {
+            return *pa;
+        }
+        let pb = b.get_pixel(bx as u32, by as u32);
+        let mut blended = [0u8; 4];
+        for c in 0..4 {
+            blended[c] = (pa[c] as f32 * (1.0 - amount) + pb[c] as f32 * amount).round() as u8;
+        }
+        Rgba(blended)
+    })
+}
+
+/// `rust-screenshot diff A B [--threshold PERCENT] [--tolerance N] [--max-shift PX] [--output FILE]`.
+/// Exits with 0 when the score is within the threshold, 1 when it is above, 2 on errors.
+pub fn run_cli(args: &[String]) -> i32 {
+    let mut files = Vec::new();
+    let mut threshold = 0.0;
+    let mut tolerance = DEFAULT_TOLERANCE;
+    let mut max_shift = 0;
+    let mut output = None;
+
+    let usage = || {
+        println!("Usage: rust-screenshot diff A B [--threshold PERCENT] [--tolerance N] [--max-shift PX] [--output DIFF.png]");
+        2
+    };
+
+    let mut index = 0;
+    while index < args.len() {
+        let value = args.get(index + 1);
+        let parsed = match (args[index].as_str(), value) {
+            ("--threshold", Some(v)) => v.parse().map(|v| threshold = v).is_ok(),
+            ("--tolerance", Some(v)) => v.parse().map(|v| tolerance = v).is_ok(),
+            ("--max-shift", Some(v)) => v.parse().map(|v| max_shift = v).is_ok(),
+            ("--output", Some(v)) => {
+                output = Some(v.clone());
+                true
+            },
+            (flag, _) if flag.starts_with("--") => false,
+            (file, _) => {
+                files.push(file.to_string());
+                index += 1;
+                continue;
+            }
+        };
+        if !parsed {
+            return usage();
+        }
+        index += 2;
+    }
+
+    if files.len() != 2 {
+        return usage();
+    }
+
+    let mut images = Vec::new();
+    for file in &files {
+        match image::open(file) {
+            Ok(image) => images.push(image.to_rgba8()),
+            Err(error) => {
+                println!("Error while opening {} -> {:?}", file, error);
+                return 2;
+            }
+        }
+    }
+
+    let offset = if max_shift > 0 { align(&images[0], &images[1], max_shift) } else { (0, 0) };
+    let result = compare(&images[0], &images[1], offset, tolerance);
+    println!("offset\t{}\t{}", result.offset.0, result.offset.1);
+    println!("changed\t{}\t{:.4}%", result.changed_pixels, result.score);
+    for (x, y, w, h) in &result.regions {
+        println!("region\t{}\t{}\t{}\t{}", x, y, w, h);
+    }
+
+    if let Some(output) = output {
+        if let Err(error) = highlight(&images[0], &result).save(&output) {
+            println!("Error while saving {} -> {:?}", output, error);
+            return 2;
+        }
+    }
+
+    if result.score > threshold { 1 } else { 0 }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompareView {
+    Diff,
+    SideBySide,
+    OnionSkin,
+}
+
+/// State of the compare window: the two captures and the last result.
+pub struct Comparison {
+    /// Name shown for each capture and its pixels
+    pub images: [Option<(String, RgbaImage)>; 2],
+    pub tolerance: u8,
+    pub max_shift: u32,
+    pub result: Option<DiffResult>,
+    pub view: CompareView,
+    pub onion_skin: f32,
+}
+
+impl Default for Comparison {
+    fn default() -> Self {
+        Comparison {
+            images: [None, None],
+            tolerance: DEFAULT_TOLERANCE,
+            max_shift: 32,
+            result: None,
+            view: CompareView::Diff,
+            onion_skin: 0.5,
+        }
+    }
+}
+
+impl Comparison {
+    pub fn set_image(&mut self, slot: usize, name: String, image: RgbaImage) {
+        self.images[slot] = Some((name, image));
+        self.result = None;
+    }
+
+    pub fn run(&mut self) {
+        if let [Some((_, a)), Some((_, b))] = &self.images {
+            let offset = if self.max_shift > 0 { align(a, b, self.max_shift) } else { (0, 0) };
+            self.result = Some(compare(a, b, offset, self.tolerance));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Smooth shapes with some detail, like a screen, large enough for several pyramid levels.
+    fn pattern(width: u32, height: u32, shift: (i32, i32)) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            let (x, y) = ((x as i32 + shift.0) as f32, (y as i32 + shift.1) as f32);
+            let value = 128.0 + 60.0 * (x / 37.0).sin() * (y / 23.0).cos() + 60.0 * ((x + 2.0 * y) / 11.0).sin();
+            Rgba([value as u8, (255.0 - value) as u8, (x as u32 % 256) as u8, 255])
+        })
+    }
+
+    #[test]
+    fn align_finds_the_shift() {
+        let a = pattern(1200, 800, (0, 0));
+        let b = pattern(1200, 800, (21, -13));
+        assert_eq!(align(&a, &b, 64), (21, -13));
+        assert_eq!(align(&a, &a, 64), (0, 0));
+    }
+}
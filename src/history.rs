@@ -0,0 +1,153 @@
+use std::{env, fs, path::PathBuf, thread::{self, JoinHandle}};
+use chrono::prelude::*;
+use eframe::{egui, epaint::TextureHandle};
+use image::RgbaImage;
+
+const THUMBNAIL_WIDTH: u32 = 160;
+
+/// Thumbnails of the captures of previous runs are only read from disk once they are shown.
+enum Thumbnail {
+    NotLoaded,
+    Loading(JoinHandle<Option<RgbaImage>>),
+    Ready(RgbaImage),
+    Failed,
+}
+
+fn thumbnail(image: &RgbaImage) -> RgbaImage {
+    let height = (image.height() * THUMBNAIL_WIDTH / image.width().max(1)).max(1);
+    image::imageops::thumbnail(image, THUMBNAIL_WIDTH, height)
+}
+
+pub struct HistoryEntry {
+    pub path: PathBuf,
+    thumbnail: Thumbnail,
+    /// Uploaded lazily the first time the entry is shown
+    pub texture: Option<TextureHandle>,
+    /// The PNG being written in the background, the file is complete once it is joined
+    saving: Option<JoinHandle<()>>,
+}
+
+impl HistoryEntry {
+    fn new(path: PathBuf, image: &RgbaImage) -> Self {
+        HistoryEntry { path, thumbnail: Thumbnail::Ready(thumbnail(image)), texture: None, saving: None }
+    }
+
+    fn from_file(path: PathBuf) -> Self {
+        HistoryEntry { path, thumbnail: Thumbnail::NotLoaded, texture: None, saving: None }
+    }
+
+    /// Waits for the background save, so that the file can be read or deleted.
+    fn wait_saved(&mut self) {
+        if let Some(saving) = self.saving.take() {
+            let _ = saving.join();
+        }
+    }
+
+    /// None until the thumbnail is decoded, which happens on a background thread for the
+    /// captures of previous runs. Call it again on the next frames while `is_loading`.
+    pub fn get_texture(&mut self, ctx: &egui::Context) -> Option<&TextureHandle> {
+        if self.texture.is_none() {
+            self.thumbnail = match std::mem::replace(&mut self.thumbnail, Thumbnail::Failed) {
+                Thumbnail::NotLoaded => {
+                    let path = self.path.clone();
+                    Thumbnail::Loading(thread::spawn(move || image::open(path).ok().map(|image| thumbnail(&image.to_rgba8()))))
+                },
+                Thumbnail::Loading(loading) if loading.is_finished() => match loading.join() {
+                    Ok(Some(thumbnail)) => Thumbnail::Ready(thumbnail),
+                    _ => {
+                        println!("Error while reading the history entry {:?}", self.path);
+                        Thumbnail::Failed
+                    },
+                },
+                thumbnail => thumbnail,
+            };
+            if let Thumbnail::Ready(thumbnail) = &self.thumbnail {
+                let color_image = egui::ColorImage::from_rgba_unmultiplied(
+                    [thumbnail.width() as usize, thumbnail.height() as usize],
+                    thumbnail.as_raw(),
+                );
+                self.texture = Some(ctx.load_texture(self.path.to_string_lossy(), color_image, Default::default()));
+            }
+        }
+        self.texture.as_ref()
+    }
+
+    pub fn is_loading(&self) -> bool {
+        matches!(self.thumbnail, Thumbnail::NotLoaded | Thumbnail::Loading(_))
+    }
+
+    pub fn load(&mut self) -> Option<RgbaImage> {
+        self.wait_saved();
+        match image::open(&self.path) {
+            Ok(image) => Some(image.to_rgba8()),
+            Err(error) => {
+                println!("Error while loading {:?} -> {:?}", self.path, error);
+                None
+            }
+        }
+    }
+}
+
+/// The last `limit` captures, kept as thumbnails in memory and as full PNGs in `directory`
+/// so that they survive a restart.
+pub struct History {
+    pub directory: PathBuf,
+    pub limit: usize,
+    pub entries: Vec<HistoryEntry>,
+}
+
+impl History {
+    pub fn new(limit: usize) -> Self {
+        let directory = env::temp_dir().join("rust-screenshot").join("history");
+        if let Err(error) = fs::create_dir_all(&directory) {
+            println!("Error while creating history directory -> {:?}", error);
+        }
+
+        let mut paths: Vec<PathBuf> = match fs::read_dir(&directory) {
+            Ok(dir) => dir
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "png"))
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        // File names start with a sortable timestamp, the oldest ones above the limit go
+        // before any file is read
+        paths.sort();
+        let kept = paths.split_off(paths.len().saturating_sub(limit));
+        for path in paths {
+            let _ = fs::remove_file(path);
+        }
+
+        let entries = kept.into_iter().map(HistoryEntry::from_file).collect();
+        History { directory, limit, entries }
+    }
+
+    pub fn push(&mut self, image: &RgbaImage) {
+        let path = self.directory.join(format!("{}.png", Utc::now().format("%Y%m%d_%H%M%S_%3f")));
+        let mut entry = HistoryEntry::new(path.clone(), image);
+
+        // Encoding a full screen PNG is slow, keep it off the UI thread
+        let image = image.clone();
+        entry.saving = Some(thread::spawn(move || {
+            if let Err(error) = image.save(&path) {
+                println!("Error while saving history entry -> {:?}", error);
+            }
+        }));
+
+        self.entries.push(entry);
+        self.trim();
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        let mut entry = self.entries.remove(index);
+        entry.wait_saved();
+        let _ = fs::remove_file(entry.path);
+    }
+
+    /// Removes the oldest entries above the limit.
+    pub fn trim(&mut self) {
+        while self.entries.len() > self.limit {
+            self.remove(0);
+        }
+    }
+}
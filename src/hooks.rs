@@ -0,0 +1,174 @@
+use std::{io::{Read, Write}, process::{Command, Stdio}, sync::mpsc::{self, Receiver, Sender}, thread, time::{Duration, Instant}};
+use chrono::prelude::*;
+
+/// A command run after every save. On Unix it runs through `sh -c` with the saved path,
+/// width and height as `$1 $2 $3`; on every platform they are also in the environment as
+/// RUST_SCREENSHOT_PATH, RUST_SCREENSHOT_WIDTH, RUST_SCREENSHOT_HEIGHT,
+/// RUST_SCREENSHOT_DISPLAY and RUST_SCREENSHOT_TIMESTAMP.
+#[derive(Debug, Clone)]
+pub struct Hook {
+    pub enabled: bool,
+    pub command: String,
+    pub timeout: f32,
+    /// Write the saved file on the command's stdin
+    pub stdin_image: bool,
+}
+
+impl Default for Hook {
+    fn default() -> Self {
+        Hook {
+            enabled: true,
+            command: String::new(),
+            timeout: 10.0,
+            stdin_image: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HookContext {
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+    pub display: String,
+}
+
+pub struct HookResult {
+    pub command: String,
+    pub status: String,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+fn shell_command(hook: &Hook, context: &HookContext) -> Command {
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = Command::new("cmd");
+        command.arg("/C").arg(&hook.command);
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(&hook.command)
+            .arg("rust-screenshot")
+            .arg(&context.path)
+            .arg(context.width.to_string())
+            .arg(context.height.to_string());
+        command
+    };
+
+    command
+        .env("RUST_SCREENSHOT_PATH", &context.path)
+        .env("RUST_SCREENSHOT_WIDTH", context.width.to_string())
+        .env("RUST_SCREENSHOT_HEIGHT", context.height.to_string())
+        .env("RUST_SCREENSHOT_DISPLAY", &context.display)
+        .env("RUST_SCREENSHOT_TIMESTAMP", Utc::now().to_rfc3339());
+    command
+}
+
+fn read_all<R: Read + Send + 'static>(reader: Option<R>) -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut output = String::new();
+        if let Some(mut reader) = reader {
+            let mut bytes = Vec::new();
+            let _ = reader.read_to_end(&mut bytes);
+            output = String::from_utf8_lossy(&bytes).to_string();
+        }
+        let _ = sender.send(output);
+    });
+    receiver
+}
+
+/// Runs the hook and waits for it, killing it after its timeout.
+pub fn run(hook: &Hook, context: &HookContext) -> HookResult {
+    let mut command = shell_command(hook, context);
+    command
+        .stdin(if hook.stdin_image { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(error) => {
+            return HookResult { command: hook.command.clone(), status: format!("Cannot start: {}", error), stdout: String::new(), stderr: String::new() };
+        }
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        let path = context.path.clone();
+        thread::spawn(move || {
+            match std::fs::read(&path) {
+                Ok(data) => { let _ = stdin.write_all(&data); },
+                Err(error) => println!("Error while reading {} for a hook -> {:?}", path, error),
+            }
+        });
+    }
+
+    // Output is read on separate threads so a chatty command can't fill the pipe and block
+    let stdout = read_all(child.stdout.take());
+    let stderr = read_all(child.stderr.take());
+
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break format!("{}", status),
+            Ok(None) if started.elapsed().as_secs_f32() > hook.timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                break format!("Killed after {:.1} seconds", hook.timeout);
+            },
+            Ok(None) => thread::sleep(Duration::from_millis(20)),
+            Err(error) => break format!("{}", error),
+        }
+    };
+
+    // Children of a killed shell may still hold the pipes open, don't wait for them forever
+    HookResult {
+        command: hook.command.clone(),
+        status,
+        stdout: stdout.recv_timeout(Duration::from_millis(500)).unwrap_or_default(),
+        stderr: stderr.recv_timeout(Duration::from_millis(500)).unwrap_or_default(),
+    }
+}
+
+/// Runs the hooks in the background and keeps a log of their results.
+pub struct HookRunner {
+    sender: Sender<HookResult>,
+    receiver: Receiver<HookResult>,
+    pub log: Vec<HookResult>,
+}
+
+impl HookRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn run_all(&self, hooks: &[Hook], context: HookContext) {
+        for hook in hooks.iter().filter(|h| h.enabled && !h.command.trim().is_empty()) {
+            let hook = hook.clone();
+            let context = context.clone();
+            let sender = self.sender.clone();
+            thread::spawn(move || {
+                let _ = sender.send(run(&hook, &context));
+            });
+        }
+    }
+
+    /// Moves the finished hooks to the log, returns true if there were any.
+    pub fn poll(&mut self) -> bool {
+        let mut received = false;
+        while let Ok(result) = self.receiver.try_recv() {
+            self.log.push(result);
+            received = true;
+        }
+        received
+    }
+}
+
+impl Default for HookRunner {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        HookRunner { sender, receiver, log: Vec::new() }
+    }
+}
@@ -0,0 +1,122 @@
+use std::{sync::{atomic::{AtomicBool, Ordering}, mpsc::{self, Receiver}, Arc}, thread, time::{Duration, Instant}};
+use chrono::prelude::*;
+use image::RgbaImage;
+
+use crate::{capture::CaptureTarget, watermark::{self, Watermark}};
+
+pub enum IntervalEvent {
+    Saved(String),
+    Skipped,
+    Error(String),
+    Finished,
+}
+
+/// A timelapse running on a background thread: every `interval` seconds the target is
+/// captured and saved in `directory`, until `shots` images are saved (0 = until stopped).
+/// The Save watermark, if any, is stamped on the saved files.
+pub struct IntervalCapture {
+    stop: Arc<AtomicBool>,
+    receiver: Receiver<IntervalEvent>,
+    pub shots: u32,
+    pub saved: u32,
+    pub skipped: u32,
+    pub last_saved: Option<String>,
+    pub last_error: Option<String>,
+    pub finished: bool,
+}
+
+impl IntervalCapture {
+    pub fn start(target: CaptureTarget, interval: f32, shots: u32, skip_identical: bool, directory: String, extension: String, watermark: Option<Watermark>) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel();
+        let thread_stop = stop.clone();
+
+        thread::spawn(move || {
+            let interval = Duration::from_secs_f32(interval);
+            let started = Utc::now().format("%d-%m-%Y_%H-%M-%S").to_string();
+            let mut previous: Option<RgbaImage> = None;
+            let mut saved = 0;
+            let mut next_shot = Instant::now();
+
+            while !thread_stop.load(Ordering::Relaxed) && (shots == 0 || saved < shots) {
+                // Sleep in small steps so that stopping is immediate
+                while Instant::now() < next_shot && !thread_stop.load(Ordering::Relaxed) {
+                    thread::sleep(next_shot.saturating_duration_since(Instant::now()).min(Duration::from_millis(50)));
+                }
+                if thread_stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                next_shot += interval;
+
+                let image = match target.capture() {
+                    Some(image) => image,
+                    None => {
+                        let _ = sender.send(IntervalEvent::Error(String::from("Capture failed")));
+                        continue;
+                    }
+                };
+
+                if skip_identical && previous.as_ref() == Some(&image) {
+                    let _ = sender.send(IntervalEvent::Skipped);
+                    continue;
+                }
+
+                let path = format!("{}/rust_screenshot_{}_{:04}{}", directory, started, saved + 1, extension);
+                // Identical frames are compared before the watermark
+                let result = match watermark.as_ref().map(|w| watermark::apply(&image, w)) {
+                    Some(Ok(stamped)) => stamped.save(&path),
+                    Some(Err(error)) => {
+                        println!("Error while applying the watermark -> {}", error);
+                        image.save(&path)
+                    },
+                    None => image.save(&path),
+                };
+                match result {
+                    Ok(_) => {
+                        saved += 1;
+                        let _ = sender.send(IntervalEvent::Saved(path));
+                    },
+                    Err(error) => {
+                        let _ = sender.send(IntervalEvent::Error(format!("{:?}", error)));
+                    }
+                }
+                previous = Some(image);
+            }
+
+            let _ = sender.send(IntervalEvent::Finished);
+        });
+
+        IntervalCapture {
+            stop,
+            receiver,
+            shots,
+            saved: 0,
+            skipped: 0,
+            last_saved: None,
+            last_error: None,
+            finished: false,
+        }
+    }
+
+    /// Collects what the capture thread did since the last call.
+    pub fn poll(&mut self) {
+        while let Ok(event) = self.receiver.try_recv() {
+            match event {
+                IntervalEvent::Saved(path) => {
+                    self.saved += 1;
+                    self.last_saved = Some(path);
+                },
+                IntervalEvent::Skipped => self.skipped += 1,
+                IntervalEvent::Error(error) => {
+                    println!("Error during interval capture -> {}", error);
+                    self.last_error = Some(error);
+                },
+                IntervalEvent::Finished => self.finished = true,
+            }
+        }
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,171 @@
+use std::{fs, path::{Path, PathBuf}};
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub const INDEX_FILE_NAME: &str = "rust_screenshot_library.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryEntry {
+    pub path: String,
+    /// RFC 3339
+    pub timestamp: String,
+    pub display: String,
+    pub width: u32,
+    pub height: u32,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub notes: String,
+}
+
+impl LibraryEntry {
+    pub fn new(path: String, display: String, width: u32, height: u32) -> Self {
+        LibraryEntry {
+            path,
+            timestamp: Utc::now().to_rfc3339(),
+            display,
+            width,
+            height,
+            tags: Vec::new(),
+            notes: String::new(),
+        }
+    }
+
+    /// Every word of the query has to match. `tag:name` only matches tags, any other word
+    /// is looked up (case-insensitive) in path, timestamp, display, tags and notes.
+    pub fn matches(&self, query: &str) -> bool {
+        query.split_whitespace().all(|word| {
+            let word = word.to_lowercase();
+            match word.strip_prefix("tag:") {
+                Some(tag) => self.tags.iter().any(|t| t.to_lowercase() == tag),
+                None => {
+                    self.path.to_lowercase().contains(&word)
+                        || self.timestamp.to_lowercase().contains(&word)
+                        || self.display.to_lowercase().contains(&word)
+                        || self.tags.iter().any(|t| t.to_lowercase().contains(&word))
+                        || self.notes.to_lowercase().contains(&word)
+                }
+            }
+        })
+    }
+}
+
+/// JSON index of the screenshots saved in a directory, stored next to them.
+pub struct Library {
+    pub directory: String,
+    pub entries: Vec<LibraryEntry>,
+}
+
+impl Library {
+    pub fn open(directory: &str) -> Self {
+        let entries = match fs::read_to_string(Self::index_path(directory)) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|error| {
+                println!("Error while reading the library index -> {:?}", error);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+
+        Library { directory: directory.to_string(), entries }
+    }
+
+    fn index_path(directory: &str) -> PathBuf {
+        Path::new(directory).join(INDEX_FILE_NAME)
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(&self.entries).map_err(|e| format!("{:?}", e))?;
+        fs::write(Self::index_path(&self.directory), json).map_err(|e| format!("{:?}", e))
+    }
+
+    /// Adds an entry, replacing the one with the same path (tags and notes are kept).
+    pub fn add(&mut self, mut entry: LibraryEntry) {
+        match self.entries.iter_mut().find(|e| e.path == entry.path) {
+            Some(existing) => {
+                entry.tags = existing.tags.clone();
+                entry.notes = existing.notes.clone();
+                *existing = entry;
+            },
+            None => self.entries.push(entry),
+        }
+    }
+
+    /// Adds the images of the directory that are not indexed yet, returns how many were added.
+    pub fn index_directory(&mut self) -> usize {
+        let mut added = 0;
+        let dir = match fs::read_dir(&self.directory) {
+            Ok(dir) => dir,
+            Err(_) => return 0,
+        };
+
+        for path in dir.filter_map(|entry| entry.ok().map(|e| e.path())) {
+            let path_string = path.to_string_lossy().to_string();
+            if self.entries.iter().any(|e| e.path == path_string) {
+                continue;
+            }
+            if let Ok((width, height)) = image::image_dimensions(&path) {
+                let mut entry = LibraryEntry::new(path_string, String::from("Unknown"), width, height);
+                if let Ok(modified) = fs::metadata(&path).and_then(|m| m.modified()) {
+                    entry.timestamp = DateTime::<Utc>::from(modified).to_rfc3339();
+                }
+                self.entries.push(entry);
+                added += 1;
+            }
+        }
+
+        added
+    }
+
+    /// Indices of the entries matching the query, newest first.
+    pub fn search(&self, query: &str) -> Vec<usize> {
+        let mut results: Vec<usize> = (0..self.entries.len())
+            .filter(|&i| self.entries[i].matches(query))
+            .collect();
+        results.sort_by(|&a, &b| self.entries[b].timestamp.cmp(&self.entries[a].timestamp));
+        results
+    }
+}
+
+/// `rust-screenshot library [--dir DIR] search [QUERY...]` prints the matching entries,
+/// `rust-screenshot library [--dir DIR] index` indexes the images already in the directory.
+pub fn run_cli(args: &[String], default_directory: &str) -> i32 {
+    let mut args = args.to_vec();
+    let mut directory = default_directory.to_string();
+    if args.len() >= 2 && args[0] == "--dir" {
+        directory = args[1].clone();
+        args.drain(0..2);
+    }
+
+    let mut library = Library::open(&directory);
+    match args.first().map(|a| a.as_str()) {
+        Some("search") => {
+            let query = args[1..].join(" ");
+            for index in library.search(&query) {
+                let entry = &library.entries[index];
+                println!(
+                    "{}\t{}\t{}x{}\t{}\t[{}]\t{}",
+                    entry.path, entry.timestamp, entry.width, entry.height, entry.display, entry.tags.join(", "), entry.notes
+                );
+            }
+            0
+        },
+        Some("index") => {
+            let added = library.index_directory();
+            match library.save() {
+                Ok(_) => {
+                    println!("Indexed {} new files in {}", added, directory);
+                    0
+                },
+                Err(error) => {
+                    println!("Error while saving the library index -> {}", error);
+                    1
+                }
+            }
+        },
+        _ => {
+            println!("Usage: rust-screenshot library [--dir DIR] search [QUERY...]");
+            println!("       rust-screenshot library [--dir DIR] index");
+            2
+        }
+    }
+}
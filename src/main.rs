@@ -1,16 +1,52 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
-use std::{env, borrow::Cow, collections::HashMap, sync::Arc};
+use std::{env, collections::{HashMap, VecDeque}, sync::Arc};
 use minifb::{self, WindowOptions, ScaleMode};
 use chrono::prelude::*;
 use eframe::{egui::{self, Pos2, Key, Modifiers, KeyboardShortcut, Window, Frame, Context, Ui, Image}, App, epaint::{Color32, Stroke, Vec2, vec2, TextureHandle, TextureManager, mutex::RwLock, TextureId }};
 use screenshots::Screen;
-use std::{thread, time::Duration};
-use arboard::{Clipboard, ImageData};
-use image::{imageops::FilterType::Nearest, Rgba, ImageBuffer, RgbaImage};
+use std::{thread, time::{Duration, Instant}};
+use arboard::Clipboard;
+use image::{imageops::FilterType::{self, Nearest}, Rgba, RgbaImage};
+
+mod beautify;
+mod capture;
+mod clipboard;
+mod crop;
+mod diff;
+mod history;
+mod hooks;
+mod interval;
+mod library;
+mod measure;
+mod overlay;
+mod pin;
+mod portal;
+mod recording;
+mod s3;
+mod stitch;
+mod transform;
+mod upload;
+mod viewer;
+mod watermark;
+mod webdav;
+mod window_capture;
 
 fn main() -> Result<(), eframe::Error> {
     env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
+
+    // Command line tools, the GUI starts when no subcommand is given
+    let args: Vec<String> = env::args().collect();
+    if args.len() > 1 {
+        let current_dir = env::current_dir().unwrap().into_os_string().into_string().unwrap();
+        match args[1].as_str() {
+            "library" => std::process::exit(library::run_cli(&args[2..], &current_dir)),
+            "diff" => std::process::exit(diff::run_cli(&args[2..])),
+            "stitch" => std::process::exit(stitch::run_cli(&args[2..])),
+            _ => (),
+        }
+    }
+
     let options = eframe::NativeOptions {
         ..Default::default()
     };
@@ -30,6 +66,7 @@ pub struct Painting {
     lines: Vec<Vec<Pos2>>,
     stroke: Stroke,
     save: bool,
+    view: viewer::ZoomPan,
 }
 
 impl Painting { 
@@ -48,18 +85,22 @@ impl Painting {
             if ui.button("Save edit").clicked() {
                 self.save = true;
             }
+            ui.separator();
+            self.view.ui_controls(ui, ui.available_size());
         })
         .response
     }
 
     pub fn ui_content(&mut self, ui: &mut egui::Ui, texture: &TextureHandle, width: f32, height: f32) -> egui::Response {
-   
-        let (mut response, painter) =
-            ui.allocate_painter(Vec2::new(width, height), egui::Sense::drag());
+        // The middle button pans, the primary one paints
+        let texture_size = texture.size_vec2();
+        let (mut response, image_to_screen) = self.view.show(ui, texture.id(), texture_size, egui::PointerButton::Middle);
+        let painter = ui.painter_at(response.rect);
 
+        // Lines are kept in canvas coordinates (width x height), whatever the zoom
         let to_screen = egui::emath::RectTransform::from_to(
-            egui::Rect::from_min_size(egui::Pos2::ZERO, response.rect.size()),
-            response.rect,
+            egui::Rect::from_min_size(egui::Pos2::ZERO, Vec2::new(width, height)),
+            *image_to_screen.to(),
         );
 
         let from_screen = to_screen.inverse();
@@ -70,14 +111,7 @@ impl Painting {
 
         let current_line = self.lines.last_mut().unwrap();
 
-        painter.add(egui::Shape::image(
-            texture.id(),
-            egui::Rect::from_min_size(response.rect.min, egui::vec2(width, height)),
-            egui::Rect::from_min_max(egui::Pos2::ZERO, egui::Pos2::new(1., 1.)),
-            egui::Color32::WHITE)
-        );
-
-        if let Some(pointer_pos) = response.interact_pointer_pos() {
+        if let (true, Some(pointer_pos)) = (response.dragged_by(egui::PointerButton::Primary), response.interact_pointer_pos()) {
             let canvas_pos = from_screen * pointer_pos;
             if current_line.last() != Some(&canvas_pos) {
                 current_line.push(canvas_pos);
@@ -110,8 +144,7 @@ impl Painting {
         Window::new(self.name())
             .open(open)
             .default_size(vec2(width, height))
-            .vscroll(true)
-            .hscroll(true)
+            .resizable(true)
             .show(ctx, |ui| self.ui(ui, texture, width, height));
     }
 
@@ -129,6 +162,7 @@ impl Default for Painting {
             lines: Default::default(),
             stroke: Stroke::new(1.0, Color32::from_rgb(25, 200, 100)),
             save: false,
+            view: viewer::ZoomPan::default(),
         }
     }
 }
@@ -136,23 +170,93 @@ impl Default for Painting {
 struct MyApp {
     screens: Vec<Screen>,
     screen_current_id: u32,
+    capture_all_screens: bool,
     screenshot_raw: Option<image::RgbaImage>,
     screenshot_built: Option<egui_extras::RetainedImage>,
     cropped_screenshot_raw: Option<image::RgbaImage>,
     cropped_screenshot_built: Option<egui_extras::RetainedImage>,
     save_directory: String,
     save_extension: String,
+    last_saved_path: Option<String>,
     auto_save: bool,
-    delay: u32,
+    delay: f32,
     delay_enable: bool,
+    delay_deadline: Option<Instant>,
+    interval_seconds: f32,
+    interval_shots: u32,
+    interval_skip_identical: bool,
+    interval_use_region: bool,
+    interval_capture: Option<interval::IntervalCapture>,
+    recording_format: recording::RecordingFormat,
+    recording_fps: u32,
+    recording_max_duration: f32,
+    recording_max_width: u32,
+    recording_use_region: bool,
+    recording: Option<recording::Recording>,
+    scroll_idle_timeout: f32,
+    scroll_capture: Option<stitch::ScrollCapture>,
     is_taking: bool,
+    is_capturing_region: bool,
+    is_capturing_window: bool,
+    capture_backend: portal::Backend,
+    portal_interactive: bool,
+    portal_capture: Option<portal::PortalCapture>,
+    window_capture: Option<window_capture::WindowCapture>,
+    windows: Vec<window_capture::WindowInfo>,
+    selected_window: Option<window_capture::WindowInfo>,
+    window_capture_options: window_capture::WindowCaptureOptions,
+    last_region: Option<(i32, i32, u32, u32)>,
     taking_refreshes: u32,
     is_cropping: bool,
-    crop_start_pos: Pos2,
-    crop_end_pos: Pos2, 
+    // The crop is a rectangle over the untouched screenshot_raw, cropped_screenshot_raw is made from it
+    crop_rect: Option<crop::Selection>,
+    crop_selection: crop::Selection,
+    crop_settings: crop::CropSettings,
     screenshot_shortcut: KeyboardShortcut,
     crop_shortcut: KeyboardShortcut,
     in_settings: bool,
+    in_history: bool,
+    in_compare: bool,
+    comparison: diff::Comparison,
+    compare_textures: Vec<TextureHandle>,
+    history: history::History,
+    in_library: bool,
+    library: Option<library::Library>,
+    library_query: String,
+    library_selected: Option<usize>,
+    library_tags: String,
+    library_notes: String,
+    upload_destination: upload::UploadDestination,
+    http_uploader: upload::HttpUploader,
+    s3_uploader: s3::S3Uploader,
+    webdav_uploader: webdav::WebDavUploader,
+    save_to_webdav: bool,
+    // Saved screenshots encoded for the upload (file name, data, content type), waiting for the
+    // running upload to finish
+    webdav_queue: VecDeque<(String, Vec<u8>, &'static str)>,
+    hooks: Vec<hooks::Hook>,
+    hook_runner: hooks::HookRunner,
+    in_hook_log: bool,
+    upload_task: Option<upload::UploadTask>,
+    upload_status: String,
+    undo_stack: Vec<(Option<RgbaImage>, Option<crop::Selection>)>,
+    resize_by_percentage: bool,
+    resize_percentage: f32,
+    resize_width: u32,
+    resize_height: u32,
+    resize_filter: FilterType,
+    padding: [u32; 4],
+    padding_color: Color32,
+    beautify_enabled: bool,
+    beautify_style: beautify::Style,
+    beautify_presets: Vec<beautify::Preset>,
+    beautify_preset_name: String,
+    watermarks: [watermark::Watermark; 3],
+    watermark_output: watermark::Output,
+    pins: Vec<pin::Pin>,
+    next_pin_id: u32,
+    view: viewer::ZoomPan,
+    measure: measure::Measure,
     painting: Painting,
     is_painting: bool,
     texture: TextureHandle,
@@ -167,6 +271,7 @@ impl MyApp {
         MyApp {
             screens: Screen::all().unwrap(),
             screen_current_id: Screen::all().unwrap()[0].display_info.id,
+            capture_all_screens: false,
             screenshot_raw: None,
             screenshot_built: None,
             cropped_screenshot_raw: None,
@@ -177,17 +282,83 @@ impl MyApp {
                 .into_string()
                 .unwrap(),
             save_extension: String::from(".png"),
+            last_saved_path: None,
             auto_save: false,
-            delay: 0,
+            delay: 0.0,
             delay_enable: false,
+            delay_deadline: None,
+            interval_seconds: 5.0,
+            interval_shots: 10,
+            interval_skip_identical: true,
+            interval_use_region: false,
+            interval_capture: None,
+            recording_format: recording::RecordingFormat::Gif,
+            recording_fps: 10,
+            recording_max_duration: 30.0,
+            recording_max_width: 960,
+            recording_use_region: false,
+            scroll_idle_timeout: 3.0,
+            scroll_capture: None,
+            recording: None,
             is_taking: false,
+            is_capturing_region: false,
+            is_capturing_window: false,
+            capture_backend: portal::Backend::Auto,
+            portal_interactive: false,
+            portal_capture: None,
+            window_capture: None,
+            windows: Vec::new(),
+            selected_window: None,
+            window_capture_options: window_capture::WindowCaptureOptions::default(),
+            last_region: None,
             taking_refreshes: 0,
             is_cropping: false,
-            crop_start_pos: Pos2::new(0.0, 0.0),
-            crop_end_pos: Pos2::new(0.0, 0.0),
+            crop_rect: None,
+            crop_selection: crop::Selection::default(),
+            crop_settings: crop::CropSettings::default(),
             screenshot_shortcut: KeyboardShortcut { modifiers: Modifiers::CTRL, key: Key::S },
             crop_shortcut: KeyboardShortcut { modifiers: Modifiers::CTRL, key: Key::R }.to_owned(),
             in_settings: false,
+            in_history: false,
+            in_compare: false,
+            comparison: diff::Comparison::default(),
+            compare_textures: Vec::new(),
+            history: history::History::new(20),
+            in_library: false,
+            library: None,
+            library_query: String::new(),
+            library_selected: None,
+            library_tags: String::new(),
+            library_notes: String::new(),
+            upload_destination: upload::UploadDestination::Http,
+            http_uploader: upload::HttpUploader::default(),
+            s3_uploader: s3::S3Uploader::default(),
+            webdav_uploader: webdav::WebDavUploader::default(),
+            save_to_webdav: false,
+            webdav_queue: VecDeque::new(),
+            hooks: Vec::new(),
+            hook_runner: hooks::HookRunner::new(),
+            in_hook_log: false,
+            upload_task: None,
+            upload_status: String::new(),
+            undo_stack: Vec::new(),
+            resize_by_percentage: true,
+            resize_percentage: 50.0,
+            resize_width: 1280,
+            resize_height: 720,
+            resize_filter: FilterType::Triangle,
+            padding: [20, 20, 20, 20],
+            padding_color: Color32::WHITE,
+            beautify_enabled: false,
+            beautify_style: beautify::Style::default(),
+            beautify_presets: beautify::load_presets(),
+            beautify_preset_name: String::new(),
+            watermarks: [watermark::Watermark::default(), watermark::Watermark::default(), watermark::Watermark::default()],
+            watermark_output: watermark::Output::Save,
+            pins: Vec::new(),
+            next_pin_id: 1,
+            view: viewer::ZoomPan::default(),
+            measure: measure::Measure::default(),
             painting: Painting::new(),
             is_painting: false,
             texture: TextureHandle::new(Arc::new(RwLock::new(TextureManager::default())) , TextureId::default()),
@@ -202,16 +373,96 @@ impl MyApp {
         self.get_screen_by_id(self.screen_current_id)
     }
 
+    // When capturing all the displays the image uses the highest scale factor among them
+    fn get_capture_scale_factor(&self) -> f32 {
+        if self.capture_all_screens {
+            capture::virtual_desktop_scale_factor(&self.screens)
+        } else {
+            self.get_current_screen().unwrap().display_info.scale_factor
+        }
+    }
+
+    // Captures the selected display, or all of them, with the screenshots crate
+    fn capture_screens(&self) -> Option<RgbaImage> {
+        if self.capture_all_screens {
+            println!("Capturing all displays");
+            capture::capture_all_screens(&self.screens)
+        } else {
+            let Some(current_screen) = self.get_current_screen() else {
+                println!("The selected display is not connected anymore");
+                return None;
+            };
+            println!("Capturing {:?}", current_screen);
+            match current_screen.capture() {
+                Ok(image) => Some(image),
+                Err(error) => {
+                    println!("Error while capturing {:?} -> {:?}", current_screen, error);
+                    None
+                }
+            }
+        }
+    }
+
     fn take_screenshot(&mut self) {
-        let current_screen = self.get_current_screen().unwrap();
+        let use_portal = match self.capture_backend {
+            portal::Backend::Auto => portal::is_wayland(),
+            portal::Backend::Screenshots => false,
+            portal::Backend::Portal => true,
+        };
+        if use_portal {
+            println!("Capturing through the desktop portal");
+            self.portal_capture = Some(portal::PortalCapture::start(self.portal_interactive));
+            return;
+        }
 
-        println!("Capturing {:?}", current_screen);
+        match self.capture_screens() {
+            Some(image) => self.set_screenshot(image),
+            None if self.capture_backend == portal::Backend::Auto && cfg!(target_os = "linux") => {
+                println!("Capture failed, trying the desktop portal");
+                self.portal_capture = Some(portal::PortalCapture::start(self.portal_interactive));
+            },
+            None => println!("Capture failed"),
+        }
+    }
 
-        if self.delay_enable && self.delay > 0 {
-            thread::sleep(Duration::from_secs(self.delay as u64));
+    // The portal answers on its own thread, the window stays hidden until it does
+    fn poll_portal_capture(&mut self, frame: &mut eframe::Frame) {
+        let result = match &mut self.portal_capture {
+            Some(portal_capture) => {
+                if !portal_capture.poll() {
+                    return;
+                }
+                portal_capture.result.take()
+            },
+            None => return,
+        };
+        self.portal_capture = None;
+
+        match result {
+            Some(Ok(Some(image))) => self.set_screenshot(image),
+            Some(Ok(None)) => println!("Screenshot cancelled in the desktop portal"),
+            Some(Err(error)) => {
+                println!("Error while capturing through the desktop portal -> {}", error);
+                // On Wayland the automatic backend tried the portal first
+                if self.capture_backend == portal::Backend::Auto && portal::is_wayland() {
+                    if let Some(image) = self.capture_screens() {
+                        self.set_screenshot(image);
+                    }
+                }
+            },
+            None => (),
         }
-            
-        let image = current_screen.capture().unwrap();
+        frame.set_visible(true);
+    }
+
+    fn set_screenshot(&mut self, image: RgbaImage) {
+        self.history.push(&image);
+        self.undo_stack.clear();
+        self.measure.clear();
+        self.last_saved_path = None;
+        self.crop_rect = None;
+        self.cropped_screenshot_raw = None;
+        self.cropped_screenshot_built = None;
         self.screenshot_raw = Some(image);
         self.screenshot_built = self.get_render_result();
 
@@ -220,53 +471,282 @@ impl MyApp {
         }
     }
 
-    fn crop_screenshot(&mut self) {
-        let current_screen = self.get_current_screen().unwrap();
-        let mut scale_factor = current_screen.display_info.scale_factor;
-        let mut compensation = 65.0;
-        if scale_factor > 1.0 { compensation = 0.0 };
+    // Loads an existing image in place of the current screenshot, dropping any crop
+    fn open_screenshot(&mut self, image: RgbaImage) {
+        self.undo_stack.clear();
+        self.measure.clear();
+        self.last_saved_path = None;
+        self.crop_rect = None;
+        self.cropped_screenshot_raw = None;
+        self.cropped_screenshot_built = None;
+        self.screenshot_raw = Some(image);
+        self.screenshot_built = self.get_render_result();
+    }
 
-        println!("Cropping {:?}", current_screen);
-        let width = ((self.crop_end_pos[0] - self.crop_start_pos[0]) * scale_factor) as u32;
-        let height = ((self.crop_end_pos[1] - self.crop_start_pos[1]) * scale_factor) as u32;
+    fn open_image_file(&mut self, path: &std::path::Path) {
+        match image::open(path) {
+            Ok(image) => self.open_screenshot(image.to_rgba8()),
+            Err(error) => println!("Error while opening {:?} -> {:?}", path, error),
+        }
+    }
 
-        println!("{} {}", self.crop_start_pos[0], self.crop_start_pos[1]);
-        println!("{} {}", self.crop_end_pos[0], self.crop_end_pos[1]);
-        if self.cropped_screenshot_raw.is_some() {
-            println!("{} {}", self.cropped_screenshot_raw.as_ref().unwrap().width(), self.cropped_screenshot_raw.as_ref().unwrap().height());
+    fn paste_image(&mut self) {
+        let pasted = Clipboard::new().and_then(|mut clipboard| clipboard.get_image());
+        match pasted {
+            Ok(img) => match RgbaImage::from_raw(img.width as u32, img.height as u32, img.bytes.into_owned()) {
+                Some(image) => self.open_screenshot(image),
+                None => println!("Invalid image in the clipboard"),
+            },
+            Err(error) => println!("No image in the clipboard -> {:?}", error),
         }
-        
-        //let image = current_screen.capture_area(self.crop_start_pos[0] as i32, self.crop_start_pos[1] as i32, width as u32, height as u32).unwrap();
-        //let image = imageops::crop_imm(&self.screenshot_raw, self.crop_start_pos[0] as i32, self.crop_start_pos[1] as i32, width as u32, height as u32);
-        let mut image = ImageBuffer::new(width as u32, height as u32); 
-        for y in 0..height {
-            for x in 0..width {
-                let original_x = (self.crop_start_pos[0] * scale_factor) as u32 + x;
-                let original_y = (self.crop_start_pos[1] - compensation) as u32 + y;
-    
-                let mut pixel: [u8; 4] = Default::default();
-                if self.cropped_screenshot_raw.is_some() {
-                    if original_x < self.cropped_screenshot_raw.as_ref().unwrap().width() && original_y < self.cropped_screenshot_raw.as_ref().unwrap().height() {
-                        pixel = self.cropped_screenshot_raw.as_ref().unwrap().get_pixel(original_x, original_y).0;
-                    }    
+    }
+
+    // Remembers the current images so that the next edit can be undone
+    fn push_undo(&mut self) {
+        self.undo_stack.push((self.screenshot_raw.clone(), self.crop_rect));
+        if self.undo_stack.len() > 20 {
+            self.undo_stack.remove(0);
+        }
+    }
+
+    fn undo(&mut self) {
+        if let Some((screenshot_raw, crop_rect)) = self.undo_stack.pop() {
+            self.screenshot_raw = screenshot_raw;
+            self.screenshot_built = self.get_render_result();
+            self.crop_rect = crop_rect;
+            self.update_crop();
+        }
+    }
+
+    // Replaces the working screenshot with an edited copy, keeping the previous one for undo
+    fn edit_screenshot<F: FnOnce(&RgbaImage) -> RgbaImage>(&mut self, edit: F) {
+        if !self.check_screenshot() {
+            return;
+        }
+        self.push_undo();
+
+        match (self.crop_rect, &self.cropped_screenshot_raw) {
+            (Some(rect), Some(c)) => {
+                let edited = edit(c);
+                if edited.dimensions() == c.dimensions() {
+                    // Written back into the original, the crop can still be widened or moved
+                    image::imageops::replace(self.screenshot_raw.as_mut().unwrap(), &edited, rect.x as i64, rect.y as i64);
                 } else {
-                    pixel = self.screenshot_raw.as_ref().unwrap().get_pixel(original_x, original_y).0;
+                    // A new size doesn't fit the original anymore, the crop becomes permanent
+                    println!("The crop is now part of the image, undo brings back the whole screenshot");
+                    self.screenshot_raw = Some(edited);
+                    self.crop_rect = None;
                 }
-                image.put_pixel(x, y, Rgba(pixel));
-            }
+            },
+            _ => self.screenshot_raw = Some(edit(self.screenshot_raw.as_ref().unwrap())),
+        }
+        self.screenshot_built = self.get_render_result();
+        self.update_crop();
+    }
+
+    fn transform_screenshot(&mut self, transform: transform::Transform) {
+        // Rotations and flips apply to the whole original and the crop follows them,
+        // so it can still be widened afterwards
+        let transformed = match (&self.screenshot_raw, self.crop_rect) {
+            (Some(s), Some(rect)) => transform::on_original(transform, rect, s.dimensions())
+                .map(|(whole, moved)| (transform::apply(s, whole), moved)),
+            _ => None,
+        };
+        match transformed {
+            Some((screenshot_raw, crop_rect)) => {
+                self.push_undo();
+                self.screenshot_raw = Some(screenshot_raw);
+                self.crop_rect = Some(crop_rect);
+                self.screenshot_built = self.get_render_result();
+                self.update_crop();
+            },
+            None => self.edit_screenshot(|image| transform::apply(image, transform)),
+        }
+    }
+
+    fn pin_screenshot(&mut self) {
+        let working_screenshot = match self.get_working_screenshot() {
+            Some(ws) => ws.clone(),
+            None => return,
+        };
+        // Open it on the current display, at most half as wide as the display
+        let info = self.get_current_screen().unwrap().display_info;
+        let zoom = (info.width as f32 * 0.5 / working_screenshot.width() as f32).min(1.0);
+        let offset = 40 * (self.pins.len() as i32 % 10);
+        match pin::Pin::open(self.next_pin_id, working_screenshot, (info.x + 40 + offset) as isize, (info.y + 40 + offset) as isize, zoom) {
+            Ok(pin) => {
+                self.pins.push(pin);
+                self.next_pin_id += 1;
+            },
+            Err(error) => println!("Error while pinning the screenshot -> {}", error),
+        }
+    }
+
+    fn get_capture_target(&self, use_region: bool) -> capture::CaptureTarget {
+        match self.last_region {
+            Some((x, y, width, height)) if use_region => capture::CaptureTarget::Region(self.screens.clone(), x, y, width, height),
+            _ if self.capture_all_screens => capture::CaptureTarget::AllScreens(self.screens.clone()),
+            _ => capture::CaptureTarget::Screen(*self.get_current_screen().unwrap()),
+        }
+    }
+
+    fn refresh_windows(&mut self) {
+        match window_capture::list_windows() {
+            Ok(windows) => {
+                // Keep the selection if the window is still there, with its new geometry
+                self.selected_window = self.selected_window.as_ref().and_then(|s| windows.iter().find(|w| w.id == s.id).cloned());
+                self.windows = windows;
+            },
+            Err(error) => println!("Error while listing the windows -> {}", error),
+        }
+    }
+
+    fn capture_window(&mut self) {
+        let window = match &self.selected_window {
+            Some(w) => w.clone(),
+            None => return,
+        };
+        self.window_capture = Some(window_capture::WindowCapture::start(window, self.window_capture_options));
+    }
+
+    // The main window stays hidden until the window capture thread is done
+    fn poll_window_capture(&mut self, frame: &mut eframe::Frame) {
+        let result = match self.window_capture.as_ref().and_then(|c| c.poll()) {
+            Some(result) => result,
+            None => return,
+        };
+        let window = self.window_capture.take().unwrap().window;
+
+        match result {
+            Ok(image) => self.set_screenshot(image),
+            Err(error) => println!("Error while capturing the window {} -> {}", window.label(), error),
+        }
+        frame.set_visible(true);
+    }
+
+    fn capture_region(&mut self) {
+        // The region is selected on a frozen capture of the current display (or of all of them)
+        let bounds = if self.capture_all_screens {
+            capture::virtual_desktop_bounds(&self.screens).map(|(min_x, min_y, max_x, max_y)| (min_x, min_y, (max_x - min_x) as u32, (max_y - min_y) as u32))
+        } else {
+            self.get_current_screen().map(|s| (s.display_info.x, s.display_info.y, s.display_info.width, s.display_info.height))
+        };
+        let (Some((origin_x, origin_y, width, height)), Some(frozen)) = (bounds, self.capture_screens()) else {
+            println!("Region capture failed");
+            return;
+        };
+
+        match overlay::select_region(&frozen, origin_x as isize, origin_y as isize, width as usize, height as usize) {
+            Ok(Some((min_x, min_y, max_x, max_y))) => {
+                // Give the overlay time to disappear before grabbing the screen again
+                thread::sleep(Duration::from_millis(100));
+
+                println!("Capturing region {} {} {} {}", min_x, min_y, max_x, max_y);
+                let region = (origin_x + min_x as i32, origin_y + min_y as i32, (max_x - min_x) as u32, (max_y - min_y) as u32);
+                self.last_region = Some(region);
+                match capture::capture_region(&self.screens, region.0, region.1, region.2, region.3) {
+                    Some(image) => self.set_screenshot(image),
+                    None => println!("Nothing to capture in the selected region"),
+                }
+            },
+            Ok(None) => println!("Region capture cancelled"),
+            Err(error) => println!("Error while showing the region overlay -> {}", error),
         }
+    }
 
-        self.cropped_screenshot_raw = Some(RgbaImage::new(width, height));
-        self.cropped_screenshot_raw = Some(image);
+    // Makes cropped_screenshot_raw match crop_rect again
+    fn update_crop(&mut self) {
+        self.cropped_screenshot_raw = match (&self.screenshot_raw, self.crop_rect) {
+            (Some(s), Some(rect)) => {
+                let rect = rect.clamp(s.dimensions());
+                self.crop_rect = Some(rect);
+                self.crop_selection = rect;
+                Some(crop::crop(s, rect))
+            },
+            _ => None,
+        };
         self.cropped_screenshot_built = self.get_cropped_render_result();
+        self.measure.clear();
+        self.last_saved_path = None;
+    }
+
+    // Crops the original screenshot, the selection is in its pixels. None shows it whole again
+    fn crop_screenshot(&mut self, selection: Option<crop::Selection>) {
+        if self.screenshot_raw.is_none() {
+            return;
+        }
+        self.push_undo();
+        self.crop_rect = selection;
+        self.update_crop();
 
         self.is_cropping = false;
 
-        if self.auto_save {
+        if self.auto_save && self.crop_rect.is_some() {
             self.save_screenshot(None);
         }
     }
 
+    fn get_working_screenshot(&self) -> Option<&RgbaImage> {
+        match &self.cropped_screenshot_raw {
+            Some(_c) => self.cropped_screenshot_raw.as_ref(),
+            None => self.screenshot_raw.as_ref(),
+        }
+    }
+
+    // The image that leaves the application: the working screenshot, beautified if enabled,
+    // with the watermark of the output it is going to
+    fn get_export_screenshot(&self, output: watermark::Output) -> Option<RgbaImage> {
+        let mut export_screenshot = self.get_working_screenshot()?.clone();
+        if self.beautify_enabled {
+            match beautify::apply(&export_screenshot, &self.beautify_style) {
+                Ok(beautified) => export_screenshot = beautified,
+                Err(error) => println!("Error while beautifying the screenshot -> {}", error),
+            }
+        }
+
+        let watermark = &self.watermarks[output as usize];
+        if watermark.enabled {
+            match watermark::apply(&export_screenshot, watermark) {
+                Ok(stamped) => export_screenshot = stamped,
+                Err(error) => println!("Error while applying the watermark -> {}", error),
+            }
+        }
+        Some(export_screenshot)
+    }
+
+    // Interval captures and recordings are saves too, their threads stamp the watermark
+    fn get_save_watermark(&self) -> Option<watermark::Watermark> {
+        let watermark = &self.watermarks[watermark::Output::Save as usize];
+        watermark.enabled.then(|| watermark.clone())
+    }
+
+    fn get_uploader(&self) -> Box<dyn upload::Uploader> {
+        match self.upload_destination {
+            upload::UploadDestination::Http => Box::new(self.http_uploader.clone()),
+            upload::UploadDestination::S3 => Box::new(self.s3_uploader.clone()),
+            upload::UploadDestination::WebDav => Box::new(self.webdav_uploader.clone()),
+        }
+    }
+
+    fn upload_screenshot(&mut self, uploader: Box<dyn upload::Uploader>) {
+        let export_screenshot = match self.get_export_screenshot(watermark::Output::Upload) {
+            Some(es) => es,
+            None => {
+                println!("No screenshot to upload");
+                return;
+            }
+        };
+
+        match upload::encode_image(&export_screenshot, &self.save_extension) {
+            Ok((data, content_type)) => {
+                let file_name = format!("rust_screenshot_{}{}", Utc::now().format("%d-%m-%Y_%H-%M-%S"), &self.save_extension);
+                self.upload_task = Some(upload::UploadTask::start(uploader, data, file_name, content_type));
+                self.upload_status = String::from("Uploading...");
+            },
+            Err(error) => self.upload_status = format!("Error while encoding the screenshot -> {}", error),
+        }
+    }
+
     fn check_screenshot(&mut self) -> bool {
         match &self.screenshot_raw {
             Some(_s) => return true, 
@@ -276,27 +756,130 @@ impl MyApp {
 
     fn save_screenshot(&mut self, prefix: Option<String>) {
         let prefix = prefix.unwrap_or(format!("{}/rust_screenshot", &self.save_directory));
-        match &self.cropped_screenshot_raw {
-            Some(s) => s.save(format!("{}_{}{}", prefix, Utc::now().format("%d-%m-%Y_%H-%M-%S"), &self.save_extension)).unwrap(),
-            None => {
-                match &self.screenshot_raw {
-                    Some(s) => s.save(format!("{}_{}{}", prefix, Utc::now().format("%d-%m-%Y_%H-%M-%S"), &self.save_extension)).unwrap(),
-                    None => return
+        let path = format!("{}_{}{}", prefix, Utc::now().format("%d-%m-%Y_%H-%M-%S"), &self.save_extension);
+        let (width, height) = match self.get_export_screenshot(watermark::Output::Save) {
+            Some(s) => { s.save(&path).unwrap(); s.dimensions() },
+            None => return
+        };
+
+        self.last_saved_path = Some(path.clone());
+        self.index_screenshot(path.clone(), width, height);
+        self.hook_runner.run_all(&self.hooks, hooks::HookContext {
+            path: path.clone(),
+            width,
+            height,
+            display: self.get_display_description(),
+        });
+
+        if self.save_to_webdav {
+            // The upload carries the Upload watermark, not the one of the saved file
+            let file_name = std::path::Path::new(&path).file_name().unwrap().to_string_lossy().to_string();
+            match self.get_export_screenshot(watermark::Output::Upload).map(|es| upload::encode_image(&es, &self.save_extension)) {
+                Some(Ok((data, content_type))) => {
+                    self.webdav_queue.push_back((file_name, data, content_type));
+                    self.upload_next_saved();
+                },
+                Some(Err(error)) => self.upload_status = format!("Error while encoding the screenshot -> {}", error),
+                None => (),
+            }
+        }
+    }
+
+    // Uploads the oldest queued save to WebDAV, unless an upload is still running
+    fn upload_next_saved(&mut self) {
+        if self.upload_task.as_ref().is_some_and(|t| t.result.is_none()) {
+            self.upload_status = format!("Uploading... ({} queued for WebDAV)", self.webdav_queue.len());
+            return;
+        }
+
+        if let Some((file_name, data, content_type)) = self.webdav_queue.pop_front() {
+            self.upload_status = format!("Uploading {} to WebDAV...", file_name);
+            self.upload_task = Some(upload::UploadTask::start(Box::new(self.webdav_uploader.clone()), data, file_name, content_type));
+        }
+    }
+
+    fn set_compare_image(&mut self, slot: usize, name: String, image: RgbaImage) {
+        self.comparison.set_image(slot, name, image);
+        self.compare_textures.clear();
+        self.in_compare = true;
+    }
+
+    fn open_compare_file(&mut self, slot: usize) {
+        let fd = rfd::FileDialog::new().add_filter("Images", &["png", "jpg", "jpeg", "gif", "bmp"]);
+        if let Some(path) = fd.pick_file() {
+            match image::open(&path) {
+                Ok(image) => self.set_compare_image(slot, path.file_name().unwrap().to_string_lossy().to_string(), image.to_rgba8()),
+                Err(error) => println!("Error while opening {:?} -> {:?}", path, error),
+            }
+        }
+    }
+
+    // Textures of the current compare view, rebuilt after every change
+    fn get_compare_textures(&mut self, ctx: &egui::Context) -> &Vec<TextureHandle> {
+        if self.compare_textures.is_empty() {
+            if let (Some(result), [Some((_, a)), Some((_, b))]) = (&self.comparison.result, &self.comparison.images) {
+                let images = match self.comparison.view {
+                    diff::CompareView::Diff => vec![diff::highlight(a, result)],
+                    diff::CompareView::SideBySide => vec![a.clone(), b.clone()],
+                    diff::CompareView::OnionSkin => vec![diff::onion_skin(a, b, result.offset, self.comparison.onion_skin)],
+                };
+                for (index, image) in images.iter().enumerate() {
+                    let color_image = egui::ColorImage::from_rgba_unmultiplied([image.width() as usize, image.height() as usize], image.as_raw());
+                    self.compare_textures.push(ctx.load_texture(format!("compare_{}", index), color_image, Default::default()));
                 }
             }
         }
+        &self.compare_textures
+    }
+
+    fn get_display_description(&self) -> String {
+        if self.capture_all_screens {
+            String::from("All displays")
+        } else {
+            format!("Screen {}", self.screen_current_id)
+        }
+    }
+
+    // The library index lives in the save directory, reopen it when the directory changes
+    fn get_library(&mut self) -> &mut library::Library {
+        if self.library.as_ref().is_none_or(|l| l.directory != self.save_directory) {
+            self.library = Some(library::Library::open(&self.save_directory));
+            self.library_selected = None;
+        }
+        self.library.as_mut().unwrap()
+    }
+
+    fn index_screenshot(&mut self, path: String, width: u32, height: u32) {
+        let entry = library::LibraryEntry::new(path, self.get_display_description(), width, height);
+        let library = self.get_library();
+        library.add(entry);
+        if let Err(error) = library.save() {
+            println!("Error while saving the library index -> {}", error);
+        }
     }
 
     fn save_as_screenshot(&mut self, prefix: Option<String>) {
         let prefix = prefix.unwrap_or(format!("{}/rust_screenshot", &self.save_directory));
-        match &self.cropped_screenshot_raw {
+        match self.get_export_screenshot(watermark::Output::Save) {
             Some(s) => s.save(format!("{}", prefix)).unwrap(),
-            None => {
-                match &self.screenshot_raw {
-                    Some(s) => s.save(format!("{}", prefix)).unwrap(),
-                    None => return
+            None => return
+        }
+        self.last_saved_path = Some(prefix);
+    }
+
+    fn copy_screenshot(&mut self, format: clipboard::CopyFormat) {
+        // Paths, HTML and Markdown point at the file, save it first if needed
+        if format.needs_saved_file() && self.last_saved_path.is_none() {
+            self.save_screenshot(None);
+        }
+
+        match self.get_export_screenshot(watermark::Output::Clipboard) {
+            Some(es) => {
+                if let Err(error) = clipboard::copy(&es, self.last_saved_path.as_deref(), format) {
+                    println!("Error while copying to clipboard! -> {}", error);
                 }
-            }
+            },
+            None => println!("No screenshot to save to clipboard")
         }
     }
 
@@ -351,12 +934,14 @@ impl MyApp {
         let mut x = x0;
         let mut y = y0;
 
+        // Lines are drawn on the original, inside the crop when there is one
+        let screenshot_raw = self.screenshot_raw.as_mut().unwrap();
+        let area = self.crop_rect.unwrap_or(crop::Selection { x: 0, y: 0, width: screenshot_raw.width(), height: screenshot_raw.height() });
+
         while x != x1 || y != y1 {
             // Disegna il pixel
-            if self.cropped_screenshot_raw.is_some() {
-                self.cropped_screenshot_raw.as_mut().unwrap().put_pixel(x as u32, y as u32, color);
-            } else {
-                self.screenshot_raw.as_mut().unwrap().put_pixel(x as u32, y as u32, color);
+            if x >= 0 && y >= 0 && (x as u32) < area.width && (y as u32) < area.height {
+                screenshot_raw.put_pixel(area.x + x as u32, area.y + y as u32, color);
             }
             
 
@@ -425,25 +1010,38 @@ impl App for MyApp {
                 ui.add(egui::Separator::default());
                 ui.add_space(10.0);
 
-                egui::ComboBox::from_label("Select display")
-                    // When created from a label the text will b shown on the side of the combobox
-                    .selected_text(format!(
+                let selected_display = if self.capture_all_screens {
+                    let (min_x, min_y, max_x, max_y) = capture::virtual_desktop_bounds(&self.screens).unwrap();
+                    format!("All displays: {:?}x{:?}", max_x - min_x, max_y - min_y)
+                } else {
+                    format!(
                         "Screen {:?}: {:?}x{:?}",
                         self.get_current_screen().unwrap().display_info.id,
                         self.get_current_screen().unwrap().display_info.width,
                         self.get_current_screen().unwrap().display_info.height
-                    )) // This is the currently selected option (in text form)
+                    )
+                };
+
+                egui::ComboBox::from_label("Select display")
+                    // When created from a label the text will b shown on the side of the combobox
+                    .selected_text(selected_display) // This is the currently selected option (in text form)
                     .show_ui(ui, |ui| {
                         // In this closure the various options can be added
                         for option in &self.screens {
                             // The first parameter is a mutable reference to allow the choice to be modified when the user selects
                             // something else. The second parameter is the actual value of the option (to be compared with the currently)
                             // selected one to allow egui to highlight the correct label. The third parameter is the string to show.
-                            ui.selectable_value(
-                                &mut self.screen_current_id,
-                                option.display_info.id,
+                            if ui.selectable_label(
+                                !self.capture_all_screens && self.screen_current_id == option.display_info.id,
                                 format!("{:?}", option.display_info),
-                            );
+                            ).clicked() {
+                                self.screen_current_id = option.display_info.id;
+                                self.capture_all_screens = false;
+                            }
+                        }
+                        // Stitches every display into a single virtual-desktop image
+                        if self.screens.len() > 1 {
+                            ui.selectable_value(&mut self.capture_all_screens, true, "All displays");
                         }
                     });
 
@@ -465,56 +1063,156 @@ impl App for MyApp {
                 if self.taking_refreshes > 1 {
                     thread::sleep(Duration::from_millis(100));
 
+                    self.crop_rect = None;
                     self.cropped_screenshot_built = None;
                     self.cropped_screenshot_raw = None;
 
-                    self.take_screenshot();
+                    if self.is_capturing_region {
+                        self.capture_region();
+                        self.is_capturing_region = false;
+                    } else if self.is_capturing_window {
+                        self.capture_window();
+                        self.is_capturing_window = false;
+                    } else {
+                        self.take_screenshot();
+                    }
                     //println!("Screenshot taken!");
                     self.is_taking = false;
-                    frame.set_visible(true);
+                    frame.set_visible(self.portal_capture.is_none() && self.window_capture.is_none());
                     //println!("Visibile");
                 }
+                if self.portal_capture.is_some() {
+                    self.poll_portal_capture(frame);
+                    ctx.request_repaint_after(Duration::from_millis(100));
+                }
+                // A portal that keeps the request open gets the window back, so it can be cancelled
+                let mut cancel_portal = false;
+                if let Some(portal_capture) = &mut self.portal_capture {
+                    if portal_capture.show_window() {
+                        frame.set_visible(true);
+                    }
+                    if portal_capture.elapsed() >= portal::SHOW_WINDOW_AFTER {
+                        ui.vertical_centered(|ui| {
+                            ui.label(egui::RichText::new(format!("Waiting for the desktop portal ({:.0}s)", portal_capture.elapsed().as_secs_f32())).heading().color(egui::Color32::from_rgb(255, 255, 255)));
+                        });
+                        cancel_portal = ui.add_sized([280., 20.], egui::Button::new("🗙  CANCEL")).clicked();
+                    }
+                }
+                if cancel_portal {
+                    println!("Desktop portal request cancelled");
+                    self.portal_capture = None;
+                }
+                if self.window_capture.is_some() {
+                    self.poll_window_capture(frame);
+                    ctx.request_repaint_after(Duration::from_millis(50));
+                }
+                // The delay runs as a countdown between frames so the UI stays responsive
+                if let Some(deadline) = self.delay_deadline {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        self.delay_deadline = None;
+                        frame.set_window_title("rust-screenshot");
+                        frame.set_visible(false);
+                        self.is_taking = true;
+                    } else {
+                        frame.set_window_title(&format!("rust-screenshot - capturing in {:.1}s", remaining.as_secs_f32()));
+                        ui.vertical_centered(|ui| {
+                            ui.label(egui::RichText::new(format!("Capturing in {:.1} seconds", remaining.as_secs_f32())).heading().color(egui::Color32::from_rgb(255, 255, 255)));
+                        });
+                        if ui.add_sized([280., 20.], egui::Button::new("🗙  CANCEL (Esc)")).clicked() || ctx.input(|i| i.key_pressed(Key::Escape)) {
+                            self.delay_deadline = None;
+                            self.is_capturing_region = false;
+                            self.is_capturing_window = false;
+                            frame.set_window_title("rust-screenshot");
+                        }
+                        ctx.request_repaint_after(Duration::from_millis(50));
+                    }
+                }
+
+                let mut start_capture = false;
                 if ui.add_sized([280., 40.], egui::Button::new("📷  TAKE A SCREENSHOT")).clicked() || ctx.input_mut(|i| i.consume_shortcut(&self.screenshot_shortcut)){
-                    frame.set_visible(false);
-                    self.is_taking = true;
+                    start_capture = true;
+                    self.is_capturing_region = false;
+                    self.is_capturing_window = false;
+                }
+                if ui.add_sized([280., 40.], egui::Button::new("⬚  CAPTURE REGION")).clicked() {
+                    start_capture = true;
+                    self.is_capturing_region = true;
+                    self.is_capturing_window = false;
+                }
+                // Single windows are read from the X server, only on Linux
+                if cfg!(target_os = "linux") {
+                    ui.horizontal(|ui| {
+                        let selected = match &self.selected_window {
+                            Some(w) => w.label(),
+                            None => String::from("Pick a window"),
+                        };
+                        let combo = egui::ComboBox::from_id_source("capture_window")
+                            .width(240.)
+                            .selected_text(selected)
+                            .show_ui(ui, |ui| {
+                                for window in &self.windows {
+                                    let is_selected = self.selected_window.as_ref().is_some_and(|w| w.id == window.id);
+                                    if ui.selectable_label(is_selected, window.label()).clicked() {
+                                        self.selected_window = Some(window.clone());
+                                    }
+                                }
+                            });
+                        // The list is read again every time it is opened
+                        let refresh = ui.button("🔄").on_hover_text("Refresh the list of windows").clicked();
+                        if combo.response.clicked() || refresh {
+                            self.refresh_windows();
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.window_capture_options.decorations, "Decorations");
+                        ui.checkbox(&mut self.window_capture_options.remove_shadow, "No shadow");
+                        ui.checkbox(&mut self.window_capture_options.composite, "XComposite")
+                            .on_hover_text("Reads the window itself, even when other windows cover it");
+                    });
+                    if ui.add_enabled(self.selected_window.is_some(), egui::Button::new("🗔  CAPTURE WINDOW").min_size(vec2(280., 40.))).clicked() {
+                        start_capture = true;
+                        self.is_capturing_region = false;
+                        self.is_capturing_window = true;
+                    }
+                }
+                if start_capture && !self.is_taking && self.portal_capture.is_none() && self.window_capture.is_none() {
+                    if self.delay_enable && self.delay > 0.0 {
+                        self.delay_deadline = Some(Instant::now() + Duration::from_secs_f32(self.delay));
+                    } else {
+                        frame.set_visible(false);
+                        self.is_taking = true;
+                    }
                 }
 
+                ui.horizontal(|ui| {
+                    if ui.add_sized([140., 20.], egui::Button::new("🗁  OPEN IMAGE...")).clicked() {
+                        let fd = rfd::FileDialog::new().add_filter("Images", &["png", "jpg", "jpeg", "gif", "bmp", "webp"]);
+                        match fd.pick_file() {
+                            Some(path) => self.open_image_file(&path),
+                            None => (),
+                        }
+                    }
+                    if ui.add_sized([140., 20.], egui::Button::new("📋  PASTE IMAGE")).clicked() {
+                        self.paste_image();
+                    }
+                });
+
                 ui.horizontal(|ui| {
 
                     if (ui.add_sized([140., 40.], egui::Button::new("✂  CROP SCREENSHOT")).clicked() || ctx.input_mut(|i| i.consume_shortcut(&self.crop_shortcut))) && self.check_screenshot() {
                         self.is_cropping = true;
 
-                        let scale_factor = self.get_current_screen().unwrap().display_info.scale_factor as usize;
-                        let mut width;
-                        let mut height;
-                        match &self.cropped_screenshot_raw {
-                            Some(_c) => { 
-                                width = self.cropped_screenshot_built.as_ref().unwrap().width().clone();
-                                height = self.cropped_screenshot_built.as_ref().unwrap().height().clone(); 
-                                if width >= self.get_current_screen().unwrap().display_info.width as usize|| height >= self.get_current_screen().unwrap().display_info.height as usize {
-                                    width = width / scale_factor;
-                                    height = height / scale_factor;
-                                }
-                            },
-                            None => {
-                                width = self.screenshot_built.as_ref().unwrap().width().clone();
-                                height = self.screenshot_built.as_ref().unwrap().height().clone(); 
-                                if width > self.get_current_screen().unwrap().display_info.width as usize|| height >= self.get_current_screen().unwrap().display_info.height as usize {
-                                    width = width / scale_factor;
-                                    height = height / scale_factor;
-                                }
-                            }
-                        };
+                        let scale_factor = self.get_capture_scale_factor() as usize;
+                        // The whole original is shown, so a crop can be widened again
+                        let mut width = self.screenshot_built.as_ref().unwrap().width().clone();
+                        let mut height = self.screenshot_built.as_ref().unwrap().height().clone(); 
+                        if width > self.get_current_screen().unwrap().display_info.width as usize|| height >= self.get_current_screen().unwrap().display_info.height as usize {
+                            width = width / scale_factor;
+                            height = height / scale_factor;
+                        }
 
-                        let resized_image: image::RgbaImage;
-                        match &self.cropped_screenshot_raw {
-                            Some(_c) => { 
-                                resized_image = image::imageops::resize(&self.cropped_screenshot_raw.as_ref().unwrap().clone(), width as u32, height as u32, Nearest);
-                            },
-                            None => {
-                                resized_image = image::imageops::resize(&self.screenshot_raw.as_ref().unwrap().clone(), width as u32, height as u32, Nearest);
-                            }
-                        };
+                        let resized_image = image::imageops::resize(&self.screenshot_raw.as_ref().unwrap().clone(), width as u32, height as u32, Nearest);
 
                         let mut buffer: Vec<u32> = vec![0; (width * height) as usize];
 
@@ -542,63 +1240,79 @@ impl App for MyApp {
 
                         let original_buffer = buffer.clone();
 
-                        let mut mouse_pos_start = None;
-                        let mut mouse_pos_end = None;
-                        let mut pressed = false;
-
-                        let mut min_x = 0;
-                        let mut min_y = 0;
-                        let mut max_x = 0;
-                        let mut max_y = 0;
+                        // The selection is kept in pixels of the image, the window may show it smaller
+                        let image = self.screenshot_raw.as_ref().unwrap().clone();
+                        let bounds = image.dimensions();
+                        let scale_x = image.width() as f32 / width as f32;
+                        let scale_y = image.height() as f32 / height as f32;
+                        // Starts from the current crop, to adjust it
+                        let mut selection: Option<crop::Selection> = self.crop_rect;
+                        let mut drag_start: Option<(f32, f32)> = None;
+                        let mut confirmed = false;
 
                         while window.is_open() && self.is_cropping {
-                            let mouse_pos_cur = window.get_mouse_pos(minifb::MouseMode::Pass).unwrap();
-                            
+                            let mouse_pos_cur = window.get_mouse_pos(minifb::MouseMode::Clamp).unwrap_or((0.0, 0.0));
+                            let mouse_image = (mouse_pos_cur.0 * scale_x, mouse_pos_cur.1 * scale_y);
+
                             if window.get_mouse_down(minifb::MouseButton::Left) {
-                                if !pressed {
-                                    mouse_pos_start = Some(mouse_pos_cur);
-                                    pressed = true;
-                                } else {
-                                    mouse_pos_end = Some(mouse_pos_cur);
+                                let start = *drag_start.get_or_insert(mouse_image);
+                                selection = Some(crop::Selection::from_drag(start, mouse_image, &self.crop_settings, bounds));
+                            } else {
+                                drag_start = None;
+                            }
+
+                            // Arrows move the selection, Alt + arrows its top left corner and Ctrl + arrows
+                            // its bottom right one, by 10 pixels with Shift
+                            let step = if window.is_key_down(minifb::Key::LeftShift) || window.is_key_down(minifb::Key::RightShift) { 10 } else { 1 };
+                            let nudge = if window.is_key_down(minifb::Key::LeftCtrl) || window.is_key_down(minifb::Key::RightCtrl) {
+                                crop::Nudge::BottomRight
+                            } else if window.is_key_down(minifb::Key::LeftAlt) || window.is_key_down(minifb::Key::RightAlt) {
+                                crop::Nudge::TopLeft
+                            } else {
+                                crop::Nudge::Move
+                            };
+                            for (key, dx, dy) in [(minifb::Key::Left, -1, 0), (minifb::Key::Right, 1, 0), (minifb::Key::Up, 0, -1), (minifb::Key::Down, 0, 1)] {
+                                if let (true, Some(s)) = (window.is_key_pressed(key, minifb::KeyRepeat::Yes), selection.as_mut()) {
+                                    s.nudge(dx * step, dy * step, nudge, bounds);
                                 }
-                            } else if pressed {
-                                pressed = false;
+                            }
+
+                            if window.is_key_pressed(minifb::Key::Enter, minifb::KeyRepeat::No) && selection.is_some_and(|s| !s.is_empty()) {
+                                confirmed = true;
+                                break;
+                            }
+                            if window.is_key_pressed(minifb::Key::Escape, minifb::KeyRepeat::No) {
+                                break;
                             }
 
                             buffer.clone_from(&original_buffer);
 
-                            if let (Some(rect_start), Some(rect_end)) = (mouse_pos_start, mouse_pos_end) {
-                                min_x = ((rect_start.0).min(rect_end.0))as usize;
-                                min_y = ((rect_start.1).min(rect_end.1)) as usize;
-                                max_x = ((rect_start.0).max(rect_end.0)) as usize;
-                                max_y = ((rect_start.1).max(rect_end.1)) as usize;
+                            if let Some(s) = selection {
+                                let min_x = ((s.x as f32 / scale_x) as usize).min(width - 1);
+                                let min_y = ((s.y as f32 / scale_y) as usize).min(height - 1);
+                                let max_x = (((s.x + s.width) as f32 / scale_x) as usize).min(width - 1);
+                                let max_y = (((s.y + s.height) as f32 / scale_y) as usize).min(height - 1);
 
                                 for x in min_x..=max_x {
-                                    buffer[(min_y * width + x) as usize] = 0xFFFFFF;
-                                    buffer[(max_y * width+ x) as usize] = 0xFFFFFF;
+                                    buffer[min_y * width + x] = 0xFFFFFF;
+                                    buffer[max_y * width + x] = 0xFFFFFF;
                                 }
                                 for y in min_y..=max_y {
-                                    buffer[(y * width + min_x) as usize] = 0xFFFFFF;
-                                    buffer[(y * width + max_x) as usize] = 0xFFFFFF;
+                                    buffer[y * width + min_x] = 0xFFFFFF;
+                                    buffer[y * width + max_x] = 0xFFFFFF;
                                 }
                             }
 
-                            if !pressed {
-                                if let (Some(_rect_start), Some(_rect_end)) = (mouse_pos_start, mouse_pos_end) {
-                                    self.crop_start_pos = Pos2::new((min_x + 1) as f32, (min_y + 67) as f32);
-                                    self.crop_end_pos = Pos2::new((max_x - 1) as f32, (max_y + 65) as f32);
-                                    self.crop_screenshot();
-
-                                    mouse_pos_start = None;
-                                    mouse_pos_end = None;
-                                    min_x = 0;
-                                    min_y = 0;
-                                    max_x = 0;
-                                    max_y = 0;
-                                    self.is_cropping = false;
-                                }
+                            if self.crop_settings.loupe {
+                                let center = ((mouse_image.0 as u32).min(bounds.0 - 1), (mouse_image.1 as u32).min(bounds.1 - 1));
+                                crop::draw_loupe(&mut buffer, width, height, &image, center, (mouse_pos_cur.0 as usize, mouse_pos_cur.1 as usize));
                             }
 
+                            window.set_title(&match selection {
+                                Some(s) => format!("Crop - {}, {} {}x{} - Enter to crop, Esc to cancel", s.x, s.y, s.width, s.height),
+                                None => format!("Crop - {}, {} - Drag to select, Esc to cancel", mouse_image.0 as u32, mouse_image.1 as u32),
+                            });
+
                             window
                                 .update_with_buffer(&buffer, width, height)
                                 .unwrap_or_else(|e| {
@@ -606,15 +1320,84 @@ impl App for MyApp {
                                 );
                             
                         }
+
+                        if let (true, Some(s)) = (confirmed, selection) {
+                            self.crop_screenshot(Some(s));
+                        }
+                        self.is_cropping = false;
                     }
 
-                    if ui.add_sized([140., 40.], egui::Button::new("🗙  CANCEL CROP")).clicked(){
-                        self.cropped_screenshot_built = None;
-                        self.cropped_screenshot_raw = None;
+                    if ui.add_sized([140., 40.], egui::Button::new("🗙  RESET CROP")).on_hover_text("Shows the whole screenshot again, annotations are kept").clicked() && self.crop_rect.is_some() {
+                        self.crop_screenshot(None);
                     }
 
                 });
 
+                ui.collapsing("Crop settings", |ui| {
+                    let settings = &mut self.crop_settings;
+                    egui::Grid::new("crop_settings").num_columns(2).show(ui, |ui| {
+                        ui.label("Aspect ratio");
+                        ui.horizontal(|ui| {
+                            egui::ComboBox::from_id_source("crop_aspect_ratio")
+                                .selected_text(settings.aspect_ratio.label())
+                                .show_ui(ui, |ui| {
+                                    for aspect_ratio in crop::AspectRatio::ALL {
+                                        ui.selectable_value(&mut settings.aspect_ratio, aspect_ratio, aspect_ratio.label());
+                                    }
+                                });
+                            if settings.aspect_ratio == crop::AspectRatio::Custom {
+                                ui.add(egui::DragValue::new(&mut settings.custom_ratio[0]).clamp_range(1..=100));
+                                ui.label(":");
+                                ui.add(egui::DragValue::new(&mut settings.custom_ratio[1]).clamp_range(1..=100));
+                            }
+                        });
+                        ui.end_row();
+
+                        ui.label("Fixed size");
+                        ui.horizontal(|ui| {
+                            let selected = match settings.fixed_size {
+                                Some([w, h]) => format!("{}x{}", w, h),
+                                None => String::from("None"),
+                            };
+                            egui::ComboBox::from_id_source("crop_fixed_size")
+                                .selected_text(selected)
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut settings.fixed_size, None, "None");
+                                    for (name, w, h) in crop::SIZE_PRESETS {
+                                        ui.selectable_value(&mut settings.fixed_size, Some([w, h]), format!("{} ({}x{})", name, w, h));
+                                    }
+                                });
+                            if let Some(size) = settings.fixed_size.as_mut() {
+                                ui.add(egui::DragValue::new(&mut size[0]).clamp_range(1..=10000));
+                                ui.label("x");
+                                ui.add(egui::DragValue::new(&mut size[1]).clamp_range(1..=10000));
+                            }
+                        });
+                        ui.end_row();
+
+                        ui.label("Loupe");
+                        ui.checkbox(&mut settings.loupe, "Magnify the pixels around the cursor");
+                        ui.end_row();
+                    });
+                    ui.label("In the crop window: Enter crops, Esc cancels, arrows move the selection, Alt + arrows its top left corner, Ctrl + arrows its bottom right corner, Shift moves by 10 pixels.");
+
+                    ui.add_space(5.0);
+                    let selection = &mut self.crop_selection;
+                    ui.horizontal(|ui| {
+                        ui.label("X");
+                        ui.add(egui::DragValue::new(&mut selection.x));
+                        ui.label("Y");
+                        ui.add(egui::DragValue::new(&mut selection.y));
+                        ui.label("W");
+                        ui.add(egui::DragValue::new(&mut selection.width).clamp_range(1..=u32::MAX));
+                        ui.label("H");
+                        ui.add(egui::DragValue::new(&mut selection.height).clamp_range(1..=u32::MAX));
+                    });
+                    if ui.add_sized([280., 20.], egui::Button::new("✂  CROP TO THESE VALUES")).on_hover_text("Pixels of the original screenshot").clicked() && self.check_screenshot() {
+                        self.crop_screenshot(Some(self.crop_selection));
+                    }
+                });
+
                 ui.add_space(10.0);
                 ui.add(egui::Separator::default());
                 ui.vertical_centered(|ui|{ui.label(egui::RichText::new("🗁  SAVE").heading().strong().color(egui::Color32::from_rgb(255, 255, 255)))});
@@ -646,31 +1429,16 @@ impl App for MyApp {
                         }
                     }
 
-                    // Il crate screenshots restituisce oggetti di tipo ImageBuffer<Rgba<u8>, Vec<u8>>
-                    // Il crate arboard restituisce oggetti di tipo ImageData { pub width: usize, pub height: usize, pub bytes: Cow<'a, [u8]>}
-                    // C'è bisogno di convertire manualmente l'ImageBuffer in ImageData perchè non c'è un cast diretto
                     if ui.add_sized([140., 20.], egui::Button::new("COPY TO CLIPBOARD")).clicked() {
+                        self.copy_screenshot(clipboard::CopyFormat::Image);
+                    }
+                });
 
-                        let mut clipboard = Clipboard::new().unwrap();
-
-                        let working_screenshot = match &self.cropped_screenshot_raw {
-                            Some(_c) => self.cropped_screenshot_raw.as_ref(),
-                            None => self.screenshot_raw.as_ref(),
-                        };
-                        
-                        match working_screenshot {
-                            Some(ws) => {
-                                let img = ImageData{
-                                    width: ws.width() as usize,
-                                    height:  ws.height() as usize,
-                                    bytes: Cow::from(ws.as_raw())
-                                };
-                                match clipboard.set_image(img) {
-                                    Ok(_) => (),
-                                    error => println!("Error while copying to clipboard! -> {:?}", error)
-                                }
-                            },
-                            None => println!("No screenshot to save to clipboard")
+                ui.menu_button("📋  COPY AS...", |ui| {
+                    for format in clipboard::CopyFormat::ALL {
+                        if ui.button(format.label()).clicked() {
+                            self.copy_screenshot(format);
+                            ui.close_menu();
                         }
                     }
                 });
@@ -694,38 +1462,435 @@ impl App for MyApp {
                 });
 
                 ui.checkbox(&mut self.auto_save, "Auto-save screenshot");
-
+                ui.checkbox(&mut self.save_to_webdav, "Also upload saved screenshots to WebDAV");
+
+                ui.collapsing("WebDAV / Nextcloud", |ui| {
+                    egui::Grid::new("webdav_settings").num_columns(2).show(ui, |ui| {
+                        ui.label("URL");
+                        ui.text_edit_singleline(&mut self.webdav_uploader.url);
+                        ui.end_row();
+                        ui.label("Username");
+                        ui.text_edit_singleline(&mut self.webdav_uploader.username);
+                        ui.end_row();
+                        ui.label("Password");
+                        ui.add(egui::TextEdit::singleline(&mut self.webdav_uploader.password).password(true));
+                        ui.end_row();
+                        ui.label("Folder");
+                        ui.text_edit_singleline(&mut self.webdav_uploader.path_template).on_hover_text("Date placeholders like %Y/%m/%d are replaced, missing folders are created");
+                        ui.end_row();
+                    });
+                    ui.checkbox(&mut self.webdav_uploader.share, "Create a public share link (Nextcloud)");
+                });
 
                 ui.add_space(10.0);
                 ui.add(egui::Separator::default());
-                ui.vertical_centered(|ui|{ui.label(egui::RichText::new("🕘  DELAY").heading().strong().color(egui::Color32::from_rgb(255, 255, 255)))});
+                ui.vertical_centered(|ui|{ui.label(egui::RichText::new("⇪  UPLOAD").heading().strong().color(egui::Color32::from_rgb(255, 255, 255)))});
                 ui.add(egui::Separator::default());
                 ui.add_space(10.0);
 
-                ui.checkbox(&mut self.delay_enable, "Enable delay");
-                
-                ui.add_sized([280., 20.],
-                    egui::DragValue::new(&mut self.delay)
-                        .speed(0.1)
-                        .clamp_range(0..=30)
-                        .prefix("Timer: ")
-                        .suffix(" seconds")
-                );
+                egui::ComboBox::from_label("Destination")
+                    .selected_text(format!("{:?}", self.upload_destination))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.upload_destination, upload::UploadDestination::Http, "Http");
+                        ui.selectable_value(&mut self.upload_destination, upload::UploadDestination::S3, "S3");
+                        ui.selectable_value(&mut self.upload_destination, upload::UploadDestination::WebDav, "WebDav");
+                    });
 
-                ui.add_space(10.0);
-                ui.add(egui::Separator::default());
-                ui.vertical_centered(|ui|{ui.label(egui::RichText::new("✏ PAINT").heading().strong().color(egui::Color32::from_rgb(255, 255, 255)))});
-                ui.add(egui::Separator::default());
-                ui.add_space(10.0);
+                if self.upload_destination == upload::UploadDestination::Http {
+                    ui.collapsing("HTTP endpoint", |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("URL");
+                            ui.text_edit_singleline(&mut self.http_uploader.url);
+                        });
+                        ui.horizontal(|ui| {
+                            ui.radio_value(&mut self.http_uploader.multipart, true, "Multipart");
+                            ui.radio_value(&mut self.http_uploader.multipart, false, "Raw body");
+                        });
+                        if self.http_uploader.multipart {
+                            ui.horizontal(|ui| {
+                                ui.label("Field name");
+                                ui.text_edit_singleline(&mut self.http_uploader.field_name);
+                            });
+                        }
+                        ui.label("Headers (one \"Name: value\" per line)");
+                        ui.add(egui::TextEdit::multiline(&mut self.http_uploader.headers).desired_rows(2));
+
+                        egui::ComboBox::from_label("Link from response")
+                            .selected_text(format!("{:?}", self.http_uploader.response_rule))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut self.http_uploader.response_rule, upload::ResponseRule::None, "None");
+                                ui.selectable_value(&mut self.http_uploader.response_rule, upload::ResponseRule::JsonPointer, "JsonPointer");
+                                ui.selectable_value(&mut self.http_uploader.response_rule, upload::ResponseRule::Regex, "Regex");
+                            });
+                        if self.http_uploader.response_rule != upload::ResponseRule::None {
+                            ui.add(egui::TextEdit::singleline(&mut self.http_uploader.response_pattern).hint_text("/data/link or https://\\S+"));
+                        }
+                    });
+                }
 
-                if ui.add_sized([280., 40.], egui::Button::new("Paint your image")).clicked() && self.check_screenshot() {
+                if self.upload_destination == upload::UploadDestination::WebDav {
+                    ui.label("WebDAV settings are in the SAVE section");
+                }
 
-                    if self.cropped_screenshot_raw.is_some() {
-                        let bg = self.cropped_screenshot_raw.as_ref().unwrap().as_flat_samples();
-                        let size = [self.cropped_screenshot_raw.as_ref().unwrap().width() as usize, self.cropped_screenshot_raw.as_ref().unwrap().height() as usize];
+                if self.upload_destination == upload::UploadDestination::S3 {
+                    ui.collapsing("S3 bucket", |ui| {
+                        egui::Grid::new("s3_settings").num_columns(2).show(ui, |ui| {
+                            ui.label("Endpoint");
+                            ui.text_edit_singleline(&mut self.s3_uploader.endpoint);
+                            ui.end_row();
+                            ui.label("Bucket");
+                            ui.text_edit_singleline(&mut self.s3_uploader.bucket);
+                            ui.end_row();
+                            ui.label("Prefix");
+                            ui.text_edit_singleline(&mut self.s3_uploader.prefix_template).on_hover_text("Date placeholders like %Y/%m/%d are replaced");
+                            ui.end_row();
+                            ui.label("Region");
+                            ui.text_edit_singleline(&mut self.s3_uploader.region);
+                            ui.end_row();
+                            ui.label("Access key");
+                            ui.text_edit_singleline(&mut self.s3_uploader.access_key);
+                            ui.end_row();
+                            ui.label("Secret key");
+                            ui.add(egui::TextEdit::singleline(&mut self.s3_uploader.secret_key).password(true));
+                            ui.end_row();
+                            ui.label("Retries");
+                            ui.add(egui::DragValue::new(&mut self.s3_uploader.retries).clamp_range(0..=10));
+                            ui.end_row();
+                        });
+                        ui.checkbox(&mut self.s3_uploader.presign, "Share a presigned URL");
+                        if self.s3_uploader.presign {
+                            ui.add(
+                                egui::DragValue::new(&mut self.s3_uploader.presign_expiration)
+                                    .speed(60)
+                                    .clamp_range(60..=604800)
+                                    .prefix("Expires after: ")
+                                    .suffix(" seconds")
+                            );
+                        }
+                    });
+                }
+
+                let uploading = self.upload_task.as_ref().is_some_and(|t| t.result.is_none());
+                if ui.add_enabled(!uploading && self.check_screenshot(), egui::Button::new("⇪  UPLOAD").min_size(vec2(280., 40.))).clicked() {
+                    self.upload_screenshot(self.get_uploader());
+                }
+
+                let mut upload_finished = false;
+                if let Some(task) = &mut self.upload_task {
+                    if task.poll() {
+                        upload_finished = true;
+                        self.upload_status = match task.result.as_ref().unwrap() {
+                            Ok(Some(link)) => {
+                                match Clipboard::new().and_then(|mut clipboard| clipboard.set_text(link.clone())) {
+                                    Ok(_) => format!("Link copied to clipboard: {}", link),
+                                    Err(error) => format!("Uploaded to {} (clipboard error: {:?})", link, error),
+                                }
+                            },
+                            Ok(None) => String::from("Upload completed"),
+                            Err(error) => format!("Upload failed: {}", error),
+                        };
+                    } else if task.result.is_none() {
+                        ctx.request_repaint_after(Duration::from_millis(200));
+                    }
+                }
+                if upload_finished && !self.webdav_queue.is_empty() {
+                    // Keep the result of the finished upload above the next one
+                    let finished = self.upload_status.clone();
+                    self.upload_next_saved();
+                    self.upload_status = format!("{}\n{}", finished, self.upload_status);
+                }
+                if !self.upload_status.is_empty() {
+                    ui.label(&self.upload_status);
+                }
+
+
+                ui.add_space(10.0);
+                ui.add(egui::Separator::default());
+                ui.vertical_centered(|ui|{ui.label(egui::RichText::new("⚙  HOOKS").heading().strong().color(egui::Color32::from_rgb(255, 255, 255)))});
+                ui.add(egui::Separator::default());
+                ui.add_space(10.0);
+
+                ui.label("Commands run after every save ($1 path, $2 width, $3 height, RUST_SCREENSHOT_* variables)");
+                let mut remove_hook = None;
+                for (index, hook) in self.hooks.iter_mut().enumerate() {
+                    ui.push_id(index, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut hook.enabled, "");
+                            ui.add(egui::TextEdit::singleline(&mut hook.command).desired_width(200.).hint_text("optipng \"$1\""));
+                            if ui.button("🗑").clicked() {
+                                remove_hook = Some(index);
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            ui.add(egui::DragValue::new(&mut hook.timeout).speed(0.5).clamp_range(0.5..=600.0).prefix("Timeout: ").suffix(" s"));
+                            ui.checkbox(&mut hook.stdin_image, "Image on stdin");
+                        });
+                    });
+                }
+                if let Some(index) = remove_hook {
+                    self.hooks.remove(index);
+                }
+
+                ui.horizontal(|ui| {
+                    if ui.add_sized([140., 20.], egui::Button::new("+ ADD HOOK")).clicked() {
+                        self.hooks.push(hooks::Hook::default());
+                    }
+                    if ui.add_sized([140., 20.], egui::Button::new(format!("SHOW LOG ({})", self.hook_runner.log.len()))).clicked() {
+                        self.in_hook_log = true;
+                    }
+                });
+
+                ui.add_space(10.0);
+                ui.add(egui::Separator::default());
+                ui.vertical_centered(|ui|{ui.label(egui::RichText::new("🗐  HISTORY").heading().strong().color(egui::Color32::from_rgb(255, 255, 255)))});
+                ui.add(egui::Separator::default());
+                ui.add_space(10.0);
+
+                if ui.add_sized([280., 40.], egui::Button::new(format!("🗐  SHOW HISTORY ({})", self.history.entries.len()))).clicked() {
+                    self.in_history = true;
+                }
+                if ui.add_sized([280., 20.], egui::Button::new("🔍  SEARCH LIBRARY")).clicked() {
+                    self.in_library = true;
+                }
+                if ui.add_sized([280., 20.], egui::Button::new("⇆  COMPARE SCREENSHOTS")).clicked() {
+                    self.in_compare = true;
+                }
+
+                ui.add_space(10.0);
+                ui.add(egui::Separator::default());
+                ui.vertical_centered(|ui|{ui.label(egui::RichText::new("🕘  DELAY").heading().strong().color(egui::Color32::from_rgb(255, 255, 255)))});
+                ui.add(egui::Separator::default());
+                ui.add_space(10.0);
+
+                ui.checkbox(&mut self.delay_enable, "Enable delay");
+                
+                ui.add_sized([280., 20.],
+                    egui::DragValue::new(&mut self.delay)
+                        .speed(0.1)
+                        .clamp_range(0.0..=30.0)
+                        .fixed_decimals(1)
+                        .prefix("Timer: ")
+                        .suffix(" seconds")
+                );
+
+                ui.add_space(10.0);
+                ui.add(egui::Separator::default());
+                ui.vertical_centered(|ui|{ui.label(egui::RichText::new("⏲  INTERVAL").heading().strong().color(egui::Color32::from_rgb(255, 255, 255)))});
+                ui.add(egui::Separator::default());
+                ui.add_space(10.0);
+
+                let mut close_interval = false;
+                match &mut self.interval_capture {
+                    Some(interval_capture) => {
+                        interval_capture.poll();
+
+                        if interval_capture.shots > 0 {
+                            ui.add_sized([280., 20.], egui::ProgressBar::new(interval_capture.saved as f32 / interval_capture.shots as f32)
+                                .text(format!("{}/{} saved", interval_capture.saved, interval_capture.shots)));
+                        } else {
+                            ui.label(format!("{} saved", interval_capture.saved));
+                        }
+                        if interval_capture.skipped > 0 {
+                            ui.label(format!("{} identical frames skipped", interval_capture.skipped));
+                        }
+                        if let Some(path) = &interval_capture.last_saved {
+                            ui.label(format!("Last: {}", path));
+                        }
+                        if let Some(error) = &interval_capture.last_error {
+                            ui.colored_label(Color32::from_rgb(252, 81, 48), error);
+                        }
+
+                        if interval_capture.finished {
+                            if ui.add_sized([280., 20.], egui::Button::new("CLOSE")).clicked() {
+                                close_interval = true;
+                            }
+                        } else {
+                            if ui.add_sized([280., 40.], egui::Button::new("⏹  STOP")).clicked() {
+                                interval_capture.stop();
+                            }
+                            ctx.request_repaint_after(Duration::from_millis(200));
+                        }
+                    },
+                    None => {
+                        ui.add_sized([280., 20.],
+                            egui::DragValue::new(&mut self.interval_seconds)
+                                .speed(0.1)
+                                .clamp_range(0.5..=3600.0)
+                                .fixed_decimals(1)
+                                .prefix("Every: ")
+                                .suffix(" seconds")
+                        );
+                        ui.add_sized([280., 20.],
+                            egui::DragValue::new(&mut self.interval_shots)
+                                .speed(1)
+                                .clamp_range(0..=10000)
+                                .prefix("Shots: ")
+                                .suffix(" (0 = until stopped)")
+                        );
+                        ui.checkbox(&mut self.interval_skip_identical, "Skip frames identical to the previous one");
+                        ui.add_enabled(self.last_region.is_some(), egui::Checkbox::new(&mut self.interval_use_region, "Use last captured region"));
+
+                        if ui.add_sized([280., 40.], egui::Button::new("▶  START INTERVAL CAPTURE")).clicked() {
+                            self.interval_capture = Some(interval::IntervalCapture::start(
+                                self.get_capture_target(self.interval_use_region),
+                                self.interval_seconds,
+                                self.interval_shots,
+                                self.interval_skip_identical,
+                                self.save_directory.clone(),
+                                self.save_extension.clone(),
+                                self.get_save_watermark(),
+                            ));
+                        }
+                    }
+                }
+                if close_interval {
+                    self.interval_capture = None;
+                }
+
+                ui.add_space(10.0);
+                ui.add(egui::Separator::default());
+                ui.vertical_centered(|ui|{ui.label(egui::RichText::new("⏺  RECORD").heading().strong().color(egui::Color32::from_rgb(255, 255, 255)))});
+                ui.add(egui::Separator::default());
+                ui.add_space(10.0);
+
+                let mut close_recording = false;
+                match &mut self.recording {
+                    Some(recording) => {
+                        recording.poll();
+
+                        if let Some(notice) = &recording.notice {
+                            ui.colored_label(Color32::from_rgb(252, 186, 3), notice);
+                        }
+                        if let Some(path) = &recording.saved {
+                            ui.label(format!("Saved: {}", path));
+                        } else if let Some(error) = &recording.error {
+                            ui.colored_label(Color32::from_rgb(252, 81, 48), error);
+                        } else if recording.encoding {
+                            ui.add_sized([280., 20.], egui::ProgressBar::new(1.0).animate(true).text(format!("Encoding {} frames...", recording.frames)));
+                        } else {
+                            ui.add_sized([280., 20.], egui::ProgressBar::new(recording.frames as f32 / (self.recording_fps as f32 * self.recording_max_duration))
+                                .text(format!("{} frames recorded", recording.frames)));
+                            if ui.add_sized([280., 40.], egui::Button::new("⏹  STOP RECORDING")).clicked() {
+                                recording.stop();
+                            }
+                        }
+
+                        if recording.is_finished() {
+                            if ui.add_sized([280., 20.], egui::Button::new("CLOSE")).clicked() {
+                                close_recording = true;
+                            }
+                        } else {
+                            ctx.request_repaint_after(Duration::from_millis(200));
+                        }
+                    },
+                    None => {
+                        egui::ComboBox::from_label("Format")
+                            .selected_text(format!("{:?}", self.recording_format))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut self.recording_format, recording::RecordingFormat::Gif, "Gif");
+                                ui.selectable_value(&mut self.recording_format, recording::RecordingFormat::Apng, "Apng");
+                            });
+                        ui.add_sized([280., 20.],
+                            egui::DragValue::new(&mut self.recording_fps)
+                                .speed(1)
+                                .clamp_range(1..=30)
+                                .prefix("FPS: ")
+                        );
+                        ui.add_sized([280., 20.],
+                            egui::DragValue::new(&mut self.recording_max_duration)
+                                .speed(0.5)
+                                .clamp_range(1.0..=300.0)
+                                .fixed_decimals(1)
+                                .prefix("Max duration: ")
+                                .suffix(" seconds")
+                        );
+                        ui.add_sized([280., 20.],
+                            egui::DragValue::new(&mut self.recording_max_width)
+                                .speed(10)
+                                .clamp_range(100..=3840)
+                                .prefix("Max width: ")
+                                .suffix(" px")
+                        );
+                        ui.add_enabled(self.last_region.is_some(), egui::Checkbox::new(&mut self.recording_use_region, "Use last captured region"));
+
+                        if ui.add_sized([280., 40.], egui::Button::new("⏺  START RECORDING")).clicked() {
+                            self.recording = Some(recording::Recording::start(
+                                self.get_capture_target(self.recording_use_region),
+                                self.recording_format,
+                                self.recording_fps,
+                                self.recording_max_duration,
+                                self.recording_max_width,
+                                self.save_directory.clone(),
+                                self.get_save_watermark(),
+                            ));
+                        }
+                    }
+                }
+                if close_recording {
+                    self.recording = None;
+                }
+
+                ui.add_space(10.0);
+                ui.add(egui::Separator::default());
+                ui.vertical_centered(|ui|{ui.label(egui::RichText::new("⇩  SCROLLING CAPTURE").heading().strong().color(egui::Color32::from_rgb(255, 255, 255)))});
+                ui.add(egui::Separator::default());
+                ui.add_space(10.0);
+
+                let mut stitched = None;
+                match &mut self.scroll_capture {
+                    Some(scroll_capture) => {
+                        if scroll_capture.poll() {
+                            stitched = scroll_capture.result.take();
+                        } else {
+                            ui.add_sized([280., 20.], egui::ProgressBar::new(1.0).animate(true).text(format!("{} frames, scroll down slowly...", scroll_capture.frames)));
+                            if ui.add_sized([280., 40.], egui::Button::new("⏹  STOP AND STITCH")).clicked() {
+                                scroll_capture.stop();
+                            }
+                            ctx.request_repaint_after(Duration::from_millis(200));
+                        }
+                    },
+                    None => {
+                        ui.label("Captures the last region while you scroll it and joins the frames in one tall image.");
+                        ui.add_sized([280., 20.],
+                            egui::DragValue::new(&mut self.scroll_idle_timeout)
+                                .speed(0.1)
+                                .clamp_range(1.0..=30.0)
+                                .fixed_decimals(1)
+                                .prefix("Stop after ")
+                                .suffix(" seconds without scrolling")
+                        );
+                        if ui.add_enabled(self.last_region.is_some(), egui::Button::new("▶  START SCROLLING CAPTURE").min_size(vec2(280., 40.))).clicked() {
+                            self.scroll_capture = Some(stitch::ScrollCapture::start(self.get_capture_target(true), self.scroll_idle_timeout));
+                        }
+                    }
+                }
+                match stitched {
+                    Some(Ok(image)) => {
+                        self.scroll_capture = None;
+                        self.set_screenshot(image);
+                    },
+                    Some(Err(error)) => {
+                        self.scroll_capture = None;
+                        println!("Error during scrolling capture -> {}", error);
+                    },
+                    None => (),
+                }
+
+                ui.add_space(10.0);
+                ui.add(egui::Separator::default());
+                ui.vertical_centered(|ui|{ui.label(egui::RichText::new("✏ PAINT").heading().strong().color(egui::Color32::from_rgb(255, 255, 255)))});
+                ui.add(egui::Separator::default());
+                ui.add_space(10.0);
+
+                if ui.add_sized([280., 40.], egui::Button::new("Paint your image")).clicked() && self.check_screenshot() {
+
+                    if self.cropped_screenshot_raw.is_some() {
+                        let bg = self.cropped_screenshot_raw.as_ref().unwrap().as_flat_samples();
+                        let size = [self.cropped_screenshot_raw.as_ref().unwrap().width() as usize, self.cropped_screenshot_raw.as_ref().unwrap().height() as usize];
                         let background = egui::ColorImage::from_rgba_premultiplied(size, bg.as_slice());
                         let texture = ctx.load_texture("Screen", background, Default::default());
                         self.texture = texture;
+                        self.painting.view.fit_to_window();
 
                         self.is_painting = true
                     } else {
@@ -734,10 +1899,371 @@ impl App for MyApp {
                         let background = egui::ColorImage::from_rgba_premultiplied(size, bg.as_slice());
                         let texture = ctx.load_texture("Screen", background, Default::default());
                         self.texture = texture;
+                        self.painting.view.fit_to_window();
 
                         self.is_painting = true;
                     }
                 }   
+
+                ui.add_space(10.0);
+                ui.add(egui::Separator::default());
+                ui.vertical_centered(|ui|{ui.label(egui::RichText::new("⟲  TRANSFORM").heading().strong().color(egui::Color32::from_rgb(255, 255, 255)))});
+                ui.add(egui::Separator::default());
+                ui.add_space(10.0);
+
+                ui.horizontal(|ui| {
+                    if ui.add_sized([90., 20.], egui::Button::new("⟳ 90°")).clicked() {
+                        self.transform_screenshot(transform::Transform::Rotate90);
+                    }
+                    if ui.add_sized([90., 20.], egui::Button::new("⟳ 180°")).clicked() {
+                        self.transform_screenshot(transform::Transform::Rotate180);
+                    }
+                    if ui.add_sized([90., 20.], egui::Button::new("⟳ 270°")).clicked() {
+                        self.transform_screenshot(transform::Transform::Rotate270);
+                    }
+                });
+                ui.horizontal(|ui| {
+                    if ui.add_sized([140., 20.], egui::Button::new("⇄  FLIP HORIZONTAL")).clicked() {
+                        self.transform_screenshot(transform::Transform::FlipHorizontal);
+                    }
+                    if ui.add_sized([140., 20.], egui::Button::new("⇅  FLIP VERTICAL")).clicked() {
+                        self.transform_screenshot(transform::Transform::FlipVertical);
+                    }
+                });
+
+                ui.add_space(5.0);
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut self.resize_by_percentage, true, "Percentage");
+                    ui.radio_value(&mut self.resize_by_percentage, false, "Exact size");
+                });
+                if self.resize_by_percentage {
+                    ui.add(egui::DragValue::new(&mut self.resize_percentage).speed(1.0).clamp_range(1.0..=400.0).suffix(" %"));
+                } else {
+                    ui.horizontal(|ui| {
+                        ui.add(egui::DragValue::new(&mut self.resize_width).speed(1).clamp_range(1..=16384).prefix("W: "));
+                        ui.add(egui::DragValue::new(&mut self.resize_height).speed(1).clamp_range(1..=16384).prefix("H: "));
+                    });
+                }
+                egui::ComboBox::from_label("Filter")
+                    .selected_text(transform::filter_name(self.resize_filter))
+                    .show_ui(ui, |ui| {
+                        for (filter, name) in transform::FILTERS {
+                            ui.selectable_value(&mut self.resize_filter, filter, name);
+                        }
+                    });
+                let resize = ui.add_sized([280., 20.], egui::Button::new("⤡  RESIZE"));
+                // Only the cropped pixels are resized
+                let resize = if self.crop_rect.is_some() { resize.on_hover_text("The crop becomes permanent, undo restores it") } else { resize };
+                if resize.clicked() {
+                    if let Some((width, height)) = self.get_working_screenshot().map(|ws| ws.dimensions()) {
+                        let (width, height) = if self.resize_by_percentage {
+                            ((width as f32 * self.resize_percentage / 100.0) as u32, (height as f32 * self.resize_percentage / 100.0) as u32)
+                        } else {
+                            (self.resize_width, self.resize_height)
+                        };
+                        self.transform_screenshot(transform::Transform::Resize(width, height, self.resize_filter));
+                    }
+                }
+
+                ui.add_space(5.0);
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut self.padding[0]).clamp_range(0..=2000).prefix("T: "));
+                    ui.add(egui::DragValue::new(&mut self.padding[1]).clamp_range(0..=2000).prefix("R: "));
+                    ui.add(egui::DragValue::new(&mut self.padding[2]).clamp_range(0..=2000).prefix("B: "));
+                    ui.add(egui::DragValue::new(&mut self.padding[3]).clamp_range(0..=2000).prefix("L: "));
+                    egui::color_picker::color_edit_button_srgba(ui, &mut self.padding_color, egui::color_picker::Alpha::OnlyBlend);
+                });
+                let extend_canvas = ui.add_sized([280., 20.], egui::Button::new("⬚  EXTEND CANVAS"));
+                // The padding goes around the crop, which can't be moved afterwards
+                let extend_canvas = if self.crop_rect.is_some() { extend_canvas.on_hover_text("The crop becomes permanent, undo restores it") } else { extend_canvas };
+                if extend_canvas.clicked() {
+                    let [r, g, b, a] = self.padding_color.to_srgba_unmultiplied();
+                    self.transform_screenshot(transform::Transform::ExtendCanvas(self.padding[0], self.padding[1], self.padding[2], self.padding[3], Rgba([r, g, b, a])));
+                }
+
+                ui.add_space(5.0);
+                if ui.add_enabled(!self.undo_stack.is_empty(), egui::Button::new("↶  UNDO").min_size(vec2(280., 20.))).clicked()
+                    || (!self.is_painting && ctx.input_mut(|i| i.consume_shortcut(&KeyboardShortcut { modifiers: Modifiers::COMMAND, key: Key::Z }))) {
+                    self.undo();
+                }
+
+                ui.add_space(10.0);
+                ui.add(egui::Separator::default());
+                ui.vertical_centered(|ui|{ui.label(egui::RichText::new("✨  BEAUTIFY").heading().strong().color(egui::Color32::from_rgb(255, 255, 255)))});
+                ui.add(egui::Separator::default());
+                ui.add_space(10.0);
+
+                ui.checkbox(&mut self.beautify_enabled, "Beautify on save, copy and upload");
+
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_source("beautify_presets")
+                        .selected_text("Load preset")
+                        .show_ui(ui, |ui| {
+                            for preset in &self.beautify_presets {
+                                if ui.selectable_label(false, &preset.name).clicked() {
+                                    self.beautify_style = preset.style.clone();
+                                    self.beautify_preset_name = preset.name.clone();
+                                }
+                            }
+                        });
+                    ui.add(egui::TextEdit::singleline(&mut self.beautify_preset_name).desired_width(100.).hint_text("Preset name"));
+                    if ui.button("💾").on_hover_text("Save the settings as a preset").clicked() && !self.beautify_preset_name.trim().is_empty() {
+                        let preset = beautify::Preset { name: self.beautify_preset_name.trim().to_string(), style: self.beautify_style.clone() };
+                        match self.beautify_presets.iter_mut().find(|p| p.name == preset.name) {
+                            Some(existing) => *existing = preset,
+                            None => self.beautify_presets.push(preset),
+                        }
+                        if let Err(error) = beautify::save_presets(&self.beautify_presets) {
+                            println!("Error while saving the beautify presets -> {}", error);
+                        }
+                    }
+                    if ui.button("🗑").on_hover_text("Delete the preset").clicked() {
+                        self.beautify_presets.retain(|p| p.name != self.beautify_preset_name.trim());
+                        if let Err(error) = beautify::save_presets(&self.beautify_presets) {
+                            println!("Error while saving the beautify presets -> {}", error);
+                        }
+                    }
+                });
+
+                ui.collapsing("Beautify settings", |ui| {
+                    let style = &mut self.beautify_style;
+                    egui::Grid::new("beautify_settings").num_columns(2).show(ui, |ui| {
+                        ui.label("Padding");
+                        ui.add(egui::DragValue::new(&mut style.padding).clamp_range(0..=1000).suffix(" px"));
+                        ui.end_row();
+
+                        ui.label("Aspect ratio");
+                        egui::ComboBox::from_id_source("beautify_aspect_ratio")
+                            .selected_text(style.aspect_ratio.label())
+                            .show_ui(ui, |ui| {
+                                for aspect_ratio in beautify::AspectRatio::ALL {
+                                    ui.selectable_value(&mut style.aspect_ratio, aspect_ratio, aspect_ratio.label());
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label("Background");
+                        ui.horizontal(|ui| {
+                            ui.radio_value(&mut style.background, beautify::Background::Solid, "Solid");
+                            ui.radio_value(&mut style.background, beautify::Background::Gradient, "Gradient");
+                            ui.radio_value(&mut style.background, beautify::Background::Image, "Image");
+                        });
+                        ui.end_row();
+
+                        match style.background {
+                            beautify::Background::Solid => {
+                                ui.label("Color");
+                                ui.color_edit_button_srgba_unmultiplied(&mut style.color);
+                                ui.end_row();
+                            },
+                            beautify::Background::Gradient => {
+                                ui.label("Colors");
+                                ui.horizontal(|ui| {
+                                    ui.color_edit_button_srgba_unmultiplied(&mut style.color);
+                                    ui.color_edit_button_srgba_unmultiplied(&mut style.gradient_color);
+                                });
+                                ui.end_row();
+                                ui.label("Angle");
+                                ui.add(egui::DragValue::new(&mut style.gradient_angle).speed(1.0).clamp_range(0.0..=360.0).suffix("°"));
+                                ui.end_row();
+                            },
+                            beautify::Background::Image => {
+                                ui.label("Image");
+                                ui.horizontal(|ui| {
+                                    ui.add(egui::TextEdit::singleline(&mut style.background_image).desired_width(120.));
+                                    if ui.button("…").clicked() {
+                                        if let Some(path) = rfd::FileDialog::new().add_filter("Images", &["png", "jpg", "jpeg", "gif", "bmp"]).pick_file() {
+                                            style.background_image = path.to_string_lossy().to_string();
+                                        }
+                                    }
+                                });
+                                ui.end_row();
+                            }
+                        }
+
+                        ui.label("Corner radius");
+                        ui.add(egui::DragValue::new(&mut style.corner_radius).clamp_range(0..=500).suffix(" px"));
+                        ui.end_row();
+
+                        ui.label("Shadow offset");
+                        ui.horizontal(|ui| {
+                            ui.add(egui::DragValue::new(&mut style.shadow_offset[0]).clamp_range(-200..=200).prefix("X: "));
+                            ui.add(egui::DragValue::new(&mut style.shadow_offset[1]).clamp_range(-200..=200).prefix("Y: "));
+                        });
+                        ui.end_row();
+
+                        ui.label("Shadow blur");
+                        ui.add(egui::DragValue::new(&mut style.shadow_blur).speed(0.5).clamp_range(0.0..=100.0).suffix(" px"));
+                        ui.end_row();
+
+                        ui.label("Shadow color");
+                        ui.color_edit_button_srgba_unmultiplied(&mut style.shadow_color);
+                        ui.end_row();
+                    });
+                });
+
+                let beautify_hover = if self.crop_rect.is_some() {
+                    "Applies the frame to the image in the editor and makes the crop permanent, undo restores both"
+                } else {
+                    "Applies the frame to the image in the editor, undo restores it"
+                };
+                if ui.add_sized([280., 20.], egui::Button::new("✨  BEAUTIFY THE IMAGE NOW")).on_hover_text(beautify_hover).clicked() {
+                    let style = self.beautify_style.clone();
+                    let beautified = self.get_working_screenshot().map(|ws| beautify::apply(ws, &style));
+                    match beautified {
+                        Some(Ok(beautified)) => self.edit_screenshot(|_| beautified),
+                        Some(Err(error)) => println!("Error while beautifying the screenshot -> {}", error),
+                        None => (),
+                    }
+                }
+
+                ui.add_space(10.0);
+                ui.add(egui::Separator::default());
+                ui.vertical_centered(|ui|{ui.label(egui::RichText::new("©  WATERMARK").heading().strong().color(egui::Color32::from_rgb(255, 255, 255)))});
+                ui.add(egui::Separator::default());
+                ui.add_space(10.0);
+
+                ui.horizontal(|ui| {
+                    for output in watermark::Output::ALL {
+                        let label = if self.watermarks[output as usize].enabled { format!("{} ✔", output.label()) } else { output.label().to_string() };
+                        ui.selectable_value(&mut self.watermark_output, output, label);
+                    }
+                });
+
+                let watermark = &mut self.watermarks[self.watermark_output as usize];
+                ui.checkbox(&mut watermark.enabled, format!("Watermark on {}", self.watermark_output.label().to_lowercase()));
+                egui::Grid::new("watermark_settings").num_columns(2).show(ui, |ui| {
+                    ui.label("Stamp");
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut watermark.stamp, watermark::Stamp::Text, "Text");
+                        ui.radio_value(&mut watermark.stamp, watermark::Stamp::Logo, "Logo");
+                    });
+                    ui.end_row();
+
+                    match watermark.stamp {
+                        watermark::Stamp::Text => {
+                            ui.label("Text");
+                            ui.horizontal(|ui| {
+                                ui.add(egui::TextEdit::singleline(&mut watermark.text).desired_width(140.));
+                                ui.color_edit_button_srgba_unmultiplied(&mut watermark.color);
+                            });
+                            ui.end_row();
+                        },
+                        watermark::Stamp::Logo => {
+                            ui.label("Logo");
+                            ui.horizontal(|ui| {
+                                ui.add(egui::TextEdit::singleline(&mut watermark.logo_path).desired_width(140.));
+                                if ui.button("…").clicked() {
+                                    if let Some(path) = rfd::FileDialog::new().add_filter("Images", &["png", "jpg", "jpeg", "gif", "bmp"]).pick_file() {
+                                        watermark.logo_path = path.to_string_lossy().to_string();
+                                    }
+                                }
+                            });
+                            ui.end_row();
+                        }
+                    }
+
+                    ui.label("Position");
+                    egui::ComboBox::from_id_source("watermark_position")
+                        .selected_text(format!("{:?}", watermark.position))
+                        .show_ui(ui, |ui| {
+                            for position in watermark::Position::ALL {
+                                ui.selectable_value(&mut watermark.position, position, format!("{:?}", position));
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("Opacity");
+                    ui.add(egui::Slider::new(&mut watermark.opacity, 0.0..=1.0));
+                    ui.end_row();
+
+                    ui.label("Rotation");
+                    ui.add(egui::DragValue::new(&mut watermark.rotation).speed(1.0).clamp_range(-180.0..=180.0).suffix("°"));
+                    ui.end_row();
+
+                    ui.label("Scale");
+                    ui.add(egui::Slider::new(&mut watermark.scale, 0.05..=1.0).custom_formatter(|n, _| format!("{:.0}% of the width", n * 100.0)));
+                    ui.end_row();
+                });
+
+                ui.add_space(10.0);
+                ui.add(egui::Separator::default());
+                ui.vertical_centered(|ui|{ui.label(egui::RichText::new("📌  PIN").heading().strong().color(egui::Color32::from_rgb(255, 255, 255)))});
+                ui.add(egui::Separator::default());
+                ui.add_space(10.0);
+
+                if ui.add_enabled(pin::SUPPORTED, egui::Button::new("📌  PIN TO SCREEN").min_size(vec2(280., 40.)))
+                    .on_hover_text("Drag to move, wheel to zoom, Ctrl + wheel for opacity, Esc to close")
+                    .on_disabled_hover_text("Pins are not available on macOS, windows can only be created on the main thread there")
+                    .clicked() {
+                    self.pin_screenshot();
+                }
+
+                self.pins.retain(|pin| pin.is_open());
+                if self.pins.iter().any(|pin| !pin.window_controls) {
+                    ui.colored_label(Color32::from_rgb(252, 186, 3), "Opacity and click-through are not available on Wayland");
+                }
+                for pin in &self.pins {
+                    let mut state = pin.state.lock().unwrap();
+                    ui.push_id(pin.id, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("Pin {} ({}x{})", pin.id, pin.width, pin.height));
+                            ui.add_enabled(pin.window_controls, egui::Checkbox::new(&mut state.click_through, "Click-through"))
+                                .on_disabled_hover_text("Not available on Wayland");
+                            if ui.button("🗙").clicked() {
+                                state.open = false;
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            ui.add_enabled(pin.window_controls, egui::Slider::new(&mut state.opacity, 0.1..=1.0).text("Opacity").fixed_decimals(2))
+                                .on_disabled_hover_text("Not available on Wayland");
+                        });
+                        ui.horizontal(|ui| {
+                            ui.add(egui::Slider::new(&mut state.zoom, 0.1..=4.0).text("Zoom").logarithmic(true).custom_formatter(|n, _| format!("{:.0}%", n * 100.0)));
+                        });
+                    });
+                }
+                if !self.pins.is_empty() {
+                    // Pins change their own zoom and opacity, keep the sliders in sync
+                    ctx.request_repaint_after(Duration::from_millis(250));
+                }
+
+                ui.add_space(10.0);
+                ui.add(egui::Separator::default());
+                ui.vertical_centered(|ui|{ui.label(egui::RichText::new("📏  MEASURE").heading().strong().color(egui::Color32::from_rgb(255, 255, 255)))});
+                ui.add(egui::Separator::default());
+                ui.add_space(10.0);
+
+                ui.checkbox(&mut self.measure.enabled, "Measure on the preview").on_hover_text("Drag with the left button to measure, the middle button pans");
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut self.measure.mode, measure::Mode::Distance, "Distance");
+                    ui.radio_value(&mut self.measure.mode, measure::Mode::Rectangle, "Rectangle");
+                    ui.color_edit_button_srgba_unmultiplied(&mut self.measure.color);
+                });
+                ui.checkbox(&mut self.measure.snap_to_edges, "Snap to edges");
+
+                let scale_factor = self.get_capture_scale_factor();
+                match self.measure.description(scale_factor) {
+                    Some(description) => {
+                        ui.label(description);
+                        ui.horizontal(|ui| {
+                            if ui.button("🔥 Burn into the image").on_hover_text("Draws the lines and the label on the image, undo removes them").clicked() {
+                                let measure = self.measure.clone();
+                                let burned = self.get_working_screenshot().map(|ws| measure::burn(ws, &measure));
+                                match burned {
+                                    Some(Ok(burned)) => self.edit_screenshot(|_| burned),
+                                    Some(Err(error)) => println!("Error while drawing the measurement -> {}", error),
+                                    None => (),
+                                }
+                            }
+                            if ui.button("Clear").clicked() {
+                                self.measure.clear();
+                            }
+                        });
+                    },
+                    None if self.measure.enabled => { ui.label("Drag on the preview to measure"); },
+                    None => (),
+                }
             });
         }); //End of left panel
 
@@ -745,7 +2271,7 @@ impl App for MyApp {
             
             let width: f32;
             let height: f32;
-            let scale_factor = self.get_current_screen().unwrap().display_info.scale_factor;
+            let scale_factor = self.get_capture_scale_factor();
 
             if self.cropped_screenshot_raw.is_some() {
                 width = self.cropped_screenshot_raw.as_ref().unwrap().width() as f32;
@@ -768,7 +2294,7 @@ impl App for MyApp {
                         color32.b() as u8,
                         color32.a() as u8,
                     ]);
-                    let scale_factor = self.get_current_screen().unwrap().display_info.scale_factor;
+                    let scale_factor = self.get_capture_scale_factor();
 
                     let first_range = 0..(line.len() - 2);
                     let second_range = 1..(line.len() - 1);
@@ -790,7 +2316,7 @@ impl App for MyApp {
             }
 
             self.screenshot_built = self.get_render_result();
-            self.cropped_screenshot_built = self.get_cropped_render_result();
+            self.update_crop();
             self.painting.save = false;
         }
 
@@ -879,6 +2405,27 @@ impl App for MyApp {
 
                 ui.separator();
 
+                // Wayland sessions need the desktop portal for full screenshots
+                if cfg!(target_os = "linux") {
+                    ui.label("Capture backend");
+                    ui.separator();
+
+                    egui::ComboBox::from_label("Full screenshots")
+                        .selected_text(self.capture_backend.label())
+                        .show_ui(ui, |ui| {
+                            for backend in portal::Backend::ALL {
+                                ui.selectable_value(&mut self.capture_backend, backend, backend.label());
+                            }
+                        });
+                    ui.add_enabled(
+                        self.capture_backend != portal::Backend::Screenshots,
+                        egui::Checkbox::new(&mut self.portal_interactive, "Let the portal ask what to capture"),
+                    );
+                    ui.label(if portal::is_wayland() { "Wayland session detected" } else { "X11 session detected" });
+
+                    ui.separator();
+                }
+
                 if ui.add_sized([140., 40.], egui::Button::new("SAVE")).clicked() {
                     self.in_settings = false;
                 }
@@ -886,29 +2433,297 @@ impl App for MyApp {
             });    
         }
 
-        //MAIN CENTRAL PANEL
-        egui::CentralPanel::default().show(ctx, |ui| {
-            egui::ScrollArea::both().show(ui, |ui| {
-                let s = &self.screenshot_built;
-                let cropped_s = &self.cropped_screenshot_built;
-                let scale_factor = self.get_current_screen().unwrap().display_info.scale_factor;
+        // Images dropped on the window are opened like "Open image..."
+        let dropped_files = ctx.input(|i| i.raw.dropped_files.clone());
+        if let Some(path) = dropped_files.iter().find_map(|f| f.path.clone()) {
+            self.open_image_file(&path);
+        }
+
+        self.hook_runner.poll();
+        if self.in_hook_log {
+            let mut clear_log = false;
+
+            egui::Window::new("Hook log").open(&mut self.in_hook_log).default_width(500.).show(ctx, |ui| {
+                if ui.button("Clear").clicked() {
+                    clear_log = true;
+                }
+                ui.separator();
 
-                match cropped_s {
-                    Some(r) => {
-                        if self.is_cropping {r.show_scaled(ui, 3.0/scale_factor);}
-                        else {r.show_scaled(ui, 1.0/scale_factor);}
+                egui::ScrollArea::vertical().max_height(400.).show(ui, |ui| {
+                    // Newest result first
+                    for result in self.hook_runner.log.iter().rev() {
+                        ui.label(egui::RichText::new(format!("{} -> {}", result.command, result.status)).strong());
+                        if !result.stdout.is_empty() {
+                            ui.label(egui::RichText::new(&result.stdout).monospace());
+                        }
+                        if !result.stderr.is_empty() {
+                            ui.label(egui::RichText::new(&result.stderr).monospace().color(Color32::from_rgb(252, 81, 48)));
+                        }
+                        ui.separator();
                     }
-                    None => {
-                        match s {
-                            Some(r) => {
-                                if self.is_cropping {r.show_scaled(ui, 1.0/scale_factor);}
-                                else {r.show_scaled(ui, 0.8/scale_factor);}
-                            }, 
-                            None => {}
+                });
+            });
+
+            if clear_log {
+                self.hook_runner.log.clear();
+            }
+            ctx.request_repaint_after(Duration::from_millis(500));
+        }
+
+        if self.in_history {
+            let mut open_entry = None;
+            let mut remove_entry = None;
+            let mut compare_entry = None;
+
+            egui::Window::new("History").open(&mut self.in_history).default_width(400.).show(ctx, |ui| {
+                if ui.add(
+                    egui::DragValue::new(&mut self.history.limit)
+                        .speed(1)
+                        .clamp_range(1..=200)
+                        .prefix("Keep last: ")
+                        .suffix(" captures")
+                ).changed() {
+                    self.history.trim();
+                }
+                ui.separator();
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    // Newest capture first
+                    for index in (0..self.history.entries.len()).rev() {
+                        let entry = &mut self.history.entries[index];
+                        ui.horizontal(|ui| {
+                            match entry.get_texture(ctx) {
+                                Some(texture) => {
+                                    ui.add(egui::Image::from_texture(texture));
+                                },
+                                None if entry.is_loading() => {
+                                    ui.spinner();
+                                    ctx.request_repaint_after(Duration::from_millis(100));
+                                },
+                                None => {
+                                    ui.label("No preview");
+                                },
+                            }
+                            ui.vertical(|ui| {
+                                ui.label(entry.path.file_name().unwrap().to_string_lossy());
+                                if ui.button("Open").clicked() {
+                                    open_entry = Some(index);
+                                }
+                                if ui.button("Save as").clicked() {
+                                    let fd = rfd::FileDialog::new();
+                                    match (fd.save_file(), entry.load()) {
+                                        (Some(path), Some(image)) => match image.save(path) {
+                                            Ok(_) => (),
+                                            error => println!("Error while exporting history entry -> {:?}", error)
+                                        },
+                                        _ => (),
+                                    }
+                                }
+                                ui.horizontal(|ui| {
+                                    if ui.button("Compare as A").clicked() {
+                                        compare_entry = Some((index, 0));
+                                    }
+                                    if ui.button("Compare as B").clicked() {
+                                        compare_entry = Some((index, 1));
+                                    }
+                                });
+                                if ui.button("🗑 Delete").clicked() {
+                                    remove_entry = Some(index);
+                                }
+                            });
+                        });
+                        ui.separator();
+                    }
+                });
+            });
+
+            if let Some(index) = open_entry {
+                if let Some(image) = self.history.entries[index].load() {
+                    self.open_screenshot(image);
+                }
+            }
+            if let Some((index, slot)) = compare_entry {
+                let entry = &mut self.history.entries[index];
+                if let Some(image) = entry.load() {
+                    let name = entry.path.file_name().unwrap().to_string_lossy().to_string();
+                    self.set_compare_image(slot, name, image);
+                }
+            }
+            if let Some(index) = remove_entry {
+                self.history.remove(index);
+            }
+        }
+
+        if self.in_compare {
+            let mut in_compare = self.in_compare;
+            let mut open_file = None;
+            let mut use_current = None;
+            let mut changed = false;
+            let textures = self.get_compare_textures(ctx).clone();
+
+            egui::Window::new("Compare").open(&mut in_compare).default_width(800.).show(ctx, |ui| {
+                for (slot, label) in ["A", "B"].iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        let name = self.comparison.images[slot].as_ref().map_or("(none)", |(name, _)| name.as_str());
+                        ui.label(format!("{}: {}", label, name));
+                        if ui.button("From file…").clicked() {
+                            open_file = Some(slot);
+                        }
+                        if ui.button("Current screenshot").clicked() {
+                            use_current = Some(slot);
+                        }
+                    });
+                }
+                ui.label("Captures can also be picked from the history window.");
+
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut self.comparison.tolerance).clamp_range(0..=255).prefix("Tolerance: "));
+                    ui.add(egui::DragValue::new(&mut self.comparison.max_shift).clamp_range(0..=500).prefix("Align up to: ").suffix(" px"));
+                    let ready = self.comparison.images.iter().all(|i| i.is_some());
+                    if ui.add_enabled(ready, egui::Button::new("⇆  COMPARE")).clicked() {
+                        self.comparison.run();
+                        changed = true;
+                    }
+                });
+
+                if let Some(result) = &self.comparison.result {
+                    ui.separator();
+                    ui.label(format!(
+                        "{:.3}% changed ({} pixels), B aligned at {:+} {:+}, {} changed regions",
+                        result.score, result.changed_pixels, result.offset.0, result.offset.1, result.regions.len()
+                    ));
+                    ui.horizontal(|ui| {
+                        changed |= ui.radio_value(&mut self.comparison.view, diff::CompareView::Diff, "Diff").changed();
+                        changed |= ui.radio_value(&mut self.comparison.view, diff::CompareView::SideBySide, "Side by side").changed();
+                        changed |= ui.radio_value(&mut self.comparison.view, diff::CompareView::OnionSkin, "Onion skin").changed();
+                        if self.comparison.view == diff::CompareView::OnionSkin {
+                            changed |= ui.add(egui::Slider::new(&mut self.comparison.onion_skin, 0.0..=1.0).text("A ↔ B")).changed();
+                        }
+                    });
+
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        let width = ui.available_width() / textures.len().max(1) as f32;
+                        ui.horizontal_top(|ui| {
+                            for texture in &textures {
+                                ui.add(egui::Image::from_texture(texture).max_width(width));
+                            }
+                        });
+                    });
+                }
+            });
+
+            if changed {
+                self.compare_textures.clear();
+                ctx.request_repaint();
+            }
+            if let Some(slot) = open_file {
+                self.open_compare_file(slot);
+            }
+            if let Some(slot) = use_current {
+                if let Some(image) = self.get_working_screenshot().cloned() {
+                    self.set_compare_image(slot, String::from("Current screenshot"), image);
+                }
+            }
+            self.in_compare = in_compare;
+        }
+
+        if self.in_library {
+            self.get_library();
+            let mut open_path = None;
+
+            egui::Window::new("Library").open(&mut self.in_library).default_width(500.).show(ctx, |ui| {
+                let library = self.library.as_mut().unwrap();
+
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut self.library_query).hint_text("Search (tag:name for tags)"));
+                    if ui.button("Index folder").clicked() {
+                        library.index_directory();
+                        if let Err(error) = library.save() {
+                            println!("Error while saving the library index -> {}", error);
+                        }
+                    }
+                });
+                ui.separator();
+
+                let results = library.search(&self.library_query);
+                ui.label(format!("{} of {} screenshots", results.len(), library.entries.len()));
+
+                egui::ScrollArea::vertical().max_height(300.).show(ui, |ui| {
+                    for index in results {
+                        let entry = &library.entries[index];
+                        let file_name = std::path::Path::new(&entry.path).file_name().map(|f| f.to_string_lossy().to_string()).unwrap_or_default();
+                        let text = format!("{}  {}x{}  {}  [{}]", file_name, entry.width, entry.height, entry.display, entry.tags.join(", "));
+                        if ui.selectable_label(self.library_selected == Some(index), text).on_hover_text(&entry.timestamp).clicked() {
+                            self.library_selected = Some(index);
+                            self.library_tags = entry.tags.join(", ");
+                            self.library_notes = entry.notes.clone();
                         }
                     }
+                });
+
+                if let Some(index) = self.library_selected.filter(|&i| i < library.entries.len()) {
+                    ui.separator();
+                    ui.label(&library.entries[index].path);
+                    ui.horizontal(|ui| {
+                        ui.label("Tags");
+                        ui.text_edit_singleline(&mut self.library_tags);
+                    });
+                    ui.label("Notes");
+                    ui.text_edit_multiline(&mut self.library_notes);
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Apply").clicked() {
+                            let entry = &mut library.entries[index];
+                            entry.tags = self.library_tags.split(',').map(|t| t.trim().to_string()).filter(|t| !t.is_empty()).collect();
+                            entry.notes = self.library_notes.clone();
+                            if let Err(error) = library.save() {
+                                println!("Error while saving the library index -> {}", error);
+                            }
+                        }
+                        if ui.button("Open").clicked() {
+                            open_path = Some(library.entries[index].path.clone());
+                        }
+                    });
                 }
             });
+
+            if let Some(path) = open_path {
+                match image::open(&path) {
+                    Ok(image) => self.open_screenshot(image.to_rgba8()),
+                    Err(error) => println!("Error while opening {} -> {:?}", path, error),
+                }
+            }
+        }
+
+        //MAIN CENTRAL PANEL
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let built = match &self.cropped_screenshot_built {
+                Some(c) => Some(c),
+                None => self.screenshot_built.as_ref(),
+            };
+
+            if let Some(r) = built {
+                if !self.is_painting {
+                    if ctx.input_mut(|i| i.consume_shortcut(&KeyboardShortcut { modifiers: Modifiers::COMMAND, key: Key::Num0 })) {
+                        self.view.fit_to_window();
+                    }
+                    if ctx.input_mut(|i| i.consume_shortcut(&KeyboardShortcut { modifiers: Modifiers::COMMAND, key: Key::Num1 })) {
+                        self.view.actual_size();
+                    }
+                }
+                self.view.ui_controls(ui, ui.available_size());
+                // While measuring the primary button measures and the middle one pans
+                let pan_button = if self.measure.enabled { egui::PointerButton::Middle } else { egui::PointerButton::Primary };
+                let (response, to_screen) = self.view.show(ui, r.texture_id(ctx), r.size_vec2(), pan_button);
+
+                let working_screenshot = match &self.cropped_screenshot_raw {
+                    Some(c) => Some(c),
+                    None => self.screenshot_raw.as_ref(),
+                };
+                if let (true, Some(ws)) = (self.measure.enabled, working_screenshot) {
+                    self.measure.ui_canvas(&response, &to_screen, ws, &ui.painter_at(response.rect));
+                }
+            }
         });
 
 
@@ -0,0 +1,240 @@
+use eframe::{egui::{emath::RectTransform, Align2, FontId, Painter, PointerButton, Pos2, Rect, Response, Stroke, Vec2}, epaint::Color32};
+use image::{imageops, Rgba, RgbaImage};
+
+use crate::watermark;
+
+/// How far from the pointer an edge is looked for, in screen points
+const SNAP_DISTANCE: f32 = 8.0;
+/// Smallest luminance step (0-255) between two pixels that counts as an edge
+const EDGE_THRESHOLD: f32 = 24.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Distance,
+    Rectangle,
+}
+
+/// Measurement between two points of the working screenshot. Points are in image pixels, on
+/// the boundaries between pixels, so a distance is a number of pixels.
+#[derive(Debug, Clone)]
+pub struct Measure {
+    pub enabled: bool,
+    pub mode: Mode,
+    pub snap_to_edges: bool,
+    /// Unmultiplied RGBA
+    pub color: [u8; 4],
+    pub start: Option<Pos2>,
+    pub end: Option<Pos2>,
+}
+
+impl Default for Measure {
+    fn default() -> Self {
+        Measure {
+            enabled: false,
+            mode: Mode::Distance,
+            snap_to_edges: true,
+            color: [255, 0, 200, 255],
+            start: None,
+            end: None,
+        }
+    }
+}
+
+fn luma(image: &RgbaImage, x: u32, y: u32) -> f32 {
+    let p = image.get_pixel(x, y);
+    0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32
+}
+
+/// Moves the point to the strongest vertical edge within `radius` pixels horizontally and to
+/// the strongest horizontal edge within `radius` pixels vertically, when there is one.
+pub fn snap(image: &RgbaImage, point: Pos2, radius: u32) -> Pos2 {
+    let (width, height) = image.dimensions();
+    let x = (point.x.round().max(0.0) as u32).min(width);
+    let y = (point.y.round().max(0.0) as u32).min(height);
+
+    // An edge at boundary b is the step between pixels b - 1 and b
+    let best_edge = |from: u32, to: u32, step: &dyn Fn(u32) -> f32| {
+        (from.max(1)..=to)
+            .map(|b| (b, step(b)))
+            .filter(|(_, strength)| *strength >= EDGE_THRESHOLD)
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(b, _)| b)
+    };
+    // The point is on a boundary too, look at the pixels on both sides of it
+    let around = |b: u32, size: u32| [b.saturating_sub(1), b.min(size - 1)];
+    let snap_x = |y: u32| best_edge(x.saturating_sub(radius), (x + radius).min(width - 1), &|b| {
+        around(y, height).iter().map(|&row| (luma(image, b - 1, row) - luma(image, b, row)).abs()).fold(0.0, f32::max)
+    }).unwrap_or(x);
+    let snap_y = |x: u32| best_edge(y.saturating_sub(radius), (y + radius).min(height - 1), &|b| {
+        around(x, width).iter().map(|&column| (luma(image, column, b - 1) - luma(image, column, b)).abs()).fold(0.0, f32::max)
+    }).unwrap_or(y);
+
+    // Near a corner only one of the two edges may pass under the point, snapping to it first
+    // brings the other one in reach
+    let snapped_y = snap_y(snap_x(y));
+    let snapped_x = snap_x(snapped_y);
+
+    Pos2::new(snapped_x as f32, snapped_y as f32)
+}
+
+impl Measure {
+    pub fn clear(&mut self) {
+        self.start = None;
+        self.end = None;
+    }
+
+    /// Horizontal and vertical extent, in physical pixels.
+    pub fn size(&self) -> Option<Vec2> {
+        Some((self.end? - self.start?).abs())
+    }
+
+    /// Short label drawn next to the measurement, in physical pixels.
+    pub fn label(&self) -> Option<String> {
+        let size = self.size()?;
+        Some(match self.mode {
+            Mode::Distance => format!("{:.1} px", size.length()),
+            Mode::Rectangle => format!("{} × {} px", size.x, size.y),
+        })
+    }
+
+    /// Physical and logical pixels, logical ones being physical divided by the display scale.
+    pub fn description(&self, scale_factor: f32) -> Option<String> {
+        let size = self.size()?;
+        let logical = size / scale_factor;
+        Some(match self.mode {
+            Mode::Distance => format!(
+                "Distance: {:.1} px ({:.1} logical)\nΔx {} px ({:.1}) · Δy {} px ({:.1})",
+                size.length(), logical.length(), size.x, logical.x, size.y, logical.y
+            ),
+            Mode::Rectangle => format!(
+                "Size: {} × {} px ({:.1} × {:.1} logical)\nFrom ({}, {})",
+                size.x, size.y, logical.x, logical.y,
+                self.start?.x.min(self.end?.x), self.start?.y.min(self.end?.y)
+            ),
+        })
+    }
+
+    /// Handles the pointer on the preview, dragging with the primary button measures, and
+    /// draws the measurement. `to_screen` maps image pixels to the screen.
+    pub fn ui_canvas(&mut self, response: &Response, to_screen: &RectTransform, image: &RgbaImage, painter: &Painter) {
+        let from_screen = to_screen.inverse();
+        let radius = (SNAP_DISTANCE / to_screen.scale().x).ceil().max(1.0) as u32;
+        let image_rect = Rect::from_min_size(Pos2::ZERO, Vec2::new(image.width() as f32, image.height() as f32));
+        let to_image = |pos: Pos2| {
+            let point = image_rect.clamp(from_screen * pos);
+            if self.snap_to_edges { snap(image, point, radius) } else { point.round() }
+        };
+
+        if response.drag_started_by(PointerButton::Primary) {
+            self.start = response.interact_pointer_pos().map(to_image);
+        }
+        if response.dragged_by(PointerButton::Primary) {
+            self.end = response.interact_pointer_pos().map(to_image);
+        }
+
+        let [r, g, b, a] = self.color;
+        let color = Color32::from_rgba_unmultiplied(r, g, b, a);
+        let stroke = Stroke::new(2.0, color);
+
+        // Where a click would land, after snapping
+        if let Some(hover) = response.hover_pos() {
+            painter.circle_stroke(to_screen * to_image(hover), 4.0, Stroke::new(1.0, color));
+        }
+
+        if let (Some(start), Some(end), Some(label)) = (self.start, self.end, self.label()) {
+            let (start, end) = (to_screen * start, to_screen * end);
+            match self.mode {
+                Mode::Distance => {
+                    painter.line_segment([start, end], stroke);
+                    painter.circle_filled(start, 3.0, color);
+                    painter.circle_filled(end, 3.0, color);
+                },
+                Mode::Rectangle => painter.rect_stroke(Rect::from_two_pos(start, end), 0.0, stroke),
+            }
+
+            let galley = painter.layout_no_wrap(label, FontId::proportional(14.0), Color32::WHITE);
+            let anchor = match self.mode {
+                Mode::Distance => start + (end - start) / 2.0,
+                Mode::Rectangle => Rect::from_two_pos(start, end).center_bottom(),
+            };
+            let label_rect = Align2::CENTER_TOP.anchor_rect(Rect::from_min_size(anchor + Vec2::new(0.0, 6.0), galley.size())).expand(3.0);
+            painter.rect_filled(label_rect, 3.0, color);
+            painter.galley(label_rect.min + Vec2::splat(3.0), galley);
+        }
+    }
+}
+
+/// Colors the pixels closer than `width / 2` to the segment.
+fn draw_segment(image: &mut RgbaImage, a: Pos2, b: Pos2, width: f32, color: Rgba<u8>) {
+    let half = width / 2.0;
+    let bounds = Rect::from_two_pos(a, b).expand(half);
+    let min_x = bounds.min.x.floor().max(0.0) as u32;
+    let min_y = bounds.min.y.floor().max(0.0) as u32;
+    let max_x = (bounds.max.x.ceil().max(0.0) as u32).min(image.width());
+    let max_y = (bounds.max.y.ceil().max(0.0) as u32).min(image.height());
+    let segment = b - a;
+    let length_sq = segment.length_sq().max(f32::EPSILON);
+
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            let center = Pos2::new(x as f32 + 0.5, y as f32 + 0.5);
+            let t = ((center - a).dot(segment) / length_sq).clamp(0.0, 1.0);
+            if (a + segment * t).distance(center) <= half {
+                image.put_pixel(x, y, color);
+            }
+        }
+    }
+}
+
+/// Draws the measurement lines and label on a copy of the image.
+pub fn burn(image: &RgbaImage, measure: &Measure) -> Result<RgbaImage, String> {
+    let (start, end) = match (measure.start, measure.end) {
+        (Some(start), Some(end)) => (start, end),
+        _ => return Err(String::from("Nothing has been measured")),
+    };
+    let label = measure.label().unwrap();
+    let color = Rgba(measure.color);
+    // Lines and text grow with the image, so they stay readable on large captures
+    let thickness = (image.width().max(image.height()) as f32 / 500.0).round().max(1.0);
+    let mut burned = image.clone();
+
+    match measure.mode {
+        Mode::Distance => {
+            draw_segment(&mut burned, start, end, thickness, color);
+            // End ticks, perpendicular to the line
+            let direction = (end - start).normalized();
+            let tick = Vec2::new(-direction.y, direction.x) * thickness * 4.0;
+            for point in [start, end] {
+                draw_segment(&mut burned, point - tick, point + tick, thickness, color);
+            }
+        },
+        Mode::Rectangle => {
+            let rect = Rect::from_two_pos(start, end);
+            for (a, b) in [
+                (rect.left_top(), rect.right_top()),
+                (rect.right_top(), rect.right_bottom()),
+                (rect.right_bottom(), rect.left_bottom()),
+                (rect.left_bottom(), rect.left_top()),
+            ] {
+                draw_segment(&mut burned, a, b, thickness, color);
+            }
+        }
+    }
+
+    let text = watermark::render_label(&label, [255, 255, 255, 255], thickness * 12.0)?;
+    let padding = thickness as u32 * 3;
+    let mut tag = RgbaImage::from_pixel(text.width() + padding * 2, text.height() + padding * 2, color);
+    imageops::overlay(&mut tag, &text, padding as i64, padding as i64);
+
+    let anchor = match measure.mode {
+        Mode::Distance => start + (end - start) / 2.0,
+        Mode::Rectangle => Rect::from_two_pos(start, end).center_bottom(),
+    };
+    // Below the anchor when there is room, above it otherwise
+    let x = (anchor.x as i64 - tag.width() as i64 / 2).clamp(0, (image.width() as i64 - tag.width() as i64).max(0));
+    let below = anchor.y as i64 + padding as i64;
+    let y = if below + tag.height() as i64 <= image.height() as i64 { below } else { (anchor.y as i64 - padding as i64 - tag.height() as i64).max(0) };
+    imageops::overlay(&mut burned, &tag, x, y);
+
+    Ok(burned)
+}
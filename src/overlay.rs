@@ -0,0 +1,79 @@
+use image::{imageops::FilterType::Nearest, RgbaImage};
+use minifb::{self, WindowOptions, ScaleMode};
+
+/// Shows the frozen capture in a borderless, always-on-top window placed at (x, y) and lets
+/// the user drag a rectangle over it. The image is shown at `width`x`height`, the returned
+/// rectangle (min_x, min_y, max_x, max_y) is in the same coordinates. Esc cancels.
+pub fn select_region(frozen: &RgbaImage, x: isize, y: isize, width: usize, height: usize) -> Result<Option<(usize, usize, usize, usize)>, String> {
+    let resized_image = image::imageops::resize(frozen, width as u32, height as u32, Nearest);
+
+    let mut buffer: Vec<u32> = vec![0; width * height];
+    resized_image.enumerate_pixels().for_each(|(x, y, pixel)| {
+        let offset = (y as usize) * width + (x as usize);
+        if offset < buffer.len() {
+            buffer[offset] = ((pixel[0] as u32) << 16) | ((pixel[1] as u32) << 8) | pixel[2] as u32;
+        }
+    });
+
+    let mut window = minifb::Window::new(
+        "Capture region",
+        width,
+        height,
+        WindowOptions {
+            borderless: true,
+            resize: false,
+            topmost: true,
+            scale_mode: ScaleMode::Center,
+            ..WindowOptions::default()
+        }
+    ).map_err(|e| e.to_string())?;
+    window.set_position(x, y);
+    window.limit_update_rate(Some(std::time::Duration::from_micros(16600)));
+
+    let original_buffer = buffer.clone();
+
+    let mut mouse_pos_start: Option<(f32, f32)> = None;
+    let mut mouse_pos_end: Option<(f32, f32)> = None;
+    let mut pressed = false;
+
+    while window.is_open() && !window.is_key_down(minifb::Key::Escape) {
+        let mouse_pos_cur = window.get_mouse_pos(minifb::MouseMode::Clamp).unwrap_or((0.0, 0.0));
+
+        if window.get_mouse_down(minifb::MouseButton::Left) {
+            if !pressed {
+                mouse_pos_start = Some(mouse_pos_cur);
+                pressed = true;
+            } else {
+                mouse_pos_end = Some(mouse_pos_cur);
+            }
+        } else if pressed {
+            pressed = false;
+        }
+
+        buffer.clone_from(&original_buffer);
+
+        if let (Some(rect_start), Some(rect_end)) = (mouse_pos_start, mouse_pos_end) {
+            let min_x = ((rect_start.0).min(rect_end.0) as usize).min(width - 1);
+            let min_y = ((rect_start.1).min(rect_end.1) as usize).min(height - 1);
+            let max_x = ((rect_start.0).max(rect_end.0) as usize).min(width - 1);
+            let max_y = ((rect_start.1).max(rect_end.1) as usize).min(height - 1);
+
+            for x in min_x..=max_x {
+                buffer[min_y * width + x] = 0xFFFFFF;
+                buffer[max_y * width + x] = 0xFFFFFF;
+            }
+            for y in min_y..=max_y {
+                buffer[y * width + min_x] = 0xFFFFFF;
+                buffer[y * width + max_x] = 0xFFFFFF;
+            }
+
+            if !pressed && max_x > min_x && max_y > min_y {
+                return Ok(Some((min_x, min_y, max_x, max_y)));
+            }
+        }
+
+        window.update_with_buffer(&buffer, width, height).map_err(|e| e.to_string())?;
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,256 @@
+use std::{env, sync::{Arc, Mutex}, thread, time::Duration};
+use image::{imageops::FilterType, RgbaImage};
+use minifb::{self, WindowOptions};
+
+use crate::portal;
+
+/// Pin windows run their own event loop on a separate thread, eframe owns the main one.
+/// macOS only allows windows on the main thread, so pins are not available there.
+pub const SUPPORTED: bool = cfg!(any(target_os = "windows", target_os = "linux"));
+
+/// Opacity and click-through are set on the native window. On Wayland minifb opens a
+/// Wayland surface, not an X11 window, and there is no protocol to change them.
+pub fn window_controls_supported() -> bool {
+    SUPPORTED && !(cfg!(target_os = "linux") && (portal::is_wayland() || env::var_os("DISPLAY").is_none()))
+}
+
+/// Settings of a pin, shared between the pin window and the main window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PinState {
+    pub opacity: f32,
+    pub zoom: f32,
+    /// Mouse clicks go to the window below, the pin can only be controlled from the main window
+    pub click_through: bool,
+    pub open: bool,
+}
+
+/// A capture shown in its own borderless, always-on-top window. Drag it to move it, the
+/// mouse wheel zooms, Ctrl + mouse wheel changes the opacity and Esc closes it.
+pub struct Pin {
+    pub id: u32,
+    pub width: u32,
+    pub height: u32,
+    pub state: Arc<Mutex<PinState>>,
+    /// Whether opacity and click-through have an effect, see `window_controls_supported`
+    pub window_controls: bool,
+}
+
+fn to_buffer(image: &RgbaImage) -> Vec<u32> {
+    image.pixels().map(|p| ((p[0] as u32) << 16) | ((p[1] as u32) << 8) | p[2] as u32).collect()
+}
+
+impl Pin {
+    pub fn open(id: u32, image: RgbaImage, x: isize, y: isize, zoom: f32) -> Result<Self, String> {
+        if !SUPPORTED {
+            return Err(String::from("Pins are not supported on this platform"));
+        }
+        let state = Arc::new(Mutex::new(PinState { opacity: 1.0, zoom, click_through: false, open: true }));
+        let thread_state = state.clone();
+        let (width, height) = image.dimensions();
+        let window_controls = window_controls_supported();
+
+        // Every pin has its own window and event loop, eframe owns the main thread
+        thread::spawn(move || {
+            // One connection to the native windowing system for the whole life of the pin
+            let display = match window_controls.then(platform::Display::open) {
+                Some(Ok(display)) => Some(display),
+                Some(Err(error)) => {
+                    println!("Opacity and click-through are not available for the pin -> {}", error);
+                    None
+                },
+                None => None,
+            };
+            let mut window: Option<minifb::Window> = None;
+            let mut buffer = Vec::new();
+            let mut size = (0, 0);
+            let mut position = (x, y);
+            let mut applied: Option<PinState> = None;
+            let mut drag_start: Option<(f32, f32)> = None;
+
+            loop {
+                let state = *thread_state.lock().unwrap();
+                if !state.open {
+                    break;
+                }
+
+                // minifb windows can't be resized from code, a new zoom level needs a new window
+                if window.is_none() || applied.is_some_and(|a| a.zoom != state.zoom) {
+                    // Close the previous window where it was moved to
+                    if let Some(previous) = window.take() {
+                        position = previous.get_position();
+                    }
+                    size = (((width as f32 * state.zoom) as usize).max(1), ((height as f32 * state.zoom) as usize).max(1));
+                    buffer = to_buffer(&image::imageops::resize(&image, size.0 as u32, size.1 as u32, FilterType::Triangle));
+
+                    let options = WindowOptions { borderless: true, resize: false, topmost: true, ..WindowOptions::default() };
+                    match minifb::Window::new(&format!("Pin {}", id), size.0, size.1, options) {
+                        Ok(mut new_window) => {
+                            new_window.set_position(position.0, position.1);
+                            new_window.limit_update_rate(Some(Duration::from_micros(16600)));
+                            window = Some(new_window);
+                            applied = None;
+                        },
+                        Err(error) => {
+                            println!("Error while opening the pin window -> {}", error);
+                            break;
+                        }
+                    }
+                }
+                let current = window.as_mut().unwrap();
+
+                if !applied.is_some_and(|a| a.opacity == state.opacity && a.click_through == state.click_through) {
+                    if let Some(display) = &display {
+                        if let Err(error) = display.apply(current.get_window_handle(), state.opacity, state.click_through) {
+                            println!("Error while updating the pin window -> {}", error);
+                        }
+                    }
+                }
+                applied = Some(state);
+
+                if !current.is_open() || current.is_key_down(minifb::Key::Escape) {
+                    break;
+                }
+
+                if let Some((_, scroll)) = current.get_scroll_wheel() {
+                    let mut state = thread_state.lock().unwrap();
+                    let ctrl = current.is_key_down(minifb::Key::LeftCtrl) || current.is_key_down(minifb::Key::RightCtrl);
+                    if ctrl && display.is_some() {
+                        state.opacity = (state.opacity + scroll.signum() * 0.05).clamp(0.1, 1.0);
+                    } else if !ctrl && scroll != 0.0 {
+                        state.zoom = (state.zoom * if scroll > 0.0 { 1.1 } else { 1.0 / 1.1 }).clamp(0.1, 4.0);
+                    }
+                }
+
+                // The window follows the mouse, so the grab point stays the same inside it
+                let mouse = current.get_mouse_pos(minifb::MouseMode::Pass);
+                match (current.get_mouse_down(minifb::MouseButton::Left), drag_start, mouse) {
+                    (true, None, Some(mouse)) => drag_start = Some(mouse),
+                    (true, Some(start), Some(mouse)) => {
+                        let (window_x, window_y) = current.get_position();
+                        current.set_position(window_x + (mouse.0 - start.0) as isize, window_y + (mouse.1 - start.1) as isize);
+                    },
+                    (false, _, _) => drag_start = None,
+                    _ => (),
+                }
+
+                if let Err(error) = current.update_with_buffer(&buffer, size.0, size.1) {
+                    println!("Error while drawing the pin -> {}", error);
+                    break;
+                }
+            }
+
+            thread_state.lock().unwrap().open = false;
+        });
+
+        Ok(Pin { id, width, height, state, window_controls })
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.state.lock().unwrap().open
+    }
+}
+
+/// Window opacity and click-through are not part of minifb, they are set on the native window.
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::os::raw::c_void;
+
+    const GWL_EXSTYLE: i32 = -20;
+    const WS_EX_LAYERED: isize = 0x80000;
+    const WS_EX_TRANSPARENT: isize = 0x20;
+    const LWA_ALPHA: u32 = 0x2;
+
+    #[link(name = "user32")]
+    extern "system" {
+        fn GetWindowLongPtrW(hwnd: *mut c_void, index: i32) -> isize;
+        fn SetWindowLongPtrW(hwnd: *mut c_void, index: i32, value: isize) -> isize;
+        fn SetLayeredWindowAttributes(hwnd: *mut c_void, key: u32, alpha: u8, flags: u32) -> i32;
+    }
+
+    pub struct Display;
+
+    impl Display {
+        pub fn open() -> Result<Self, String> {
+            Ok(Display)
+        }
+
+        pub fn apply(&self, handle: *mut c_void, opacity: f32, click_through: bool) -> Result<(), String> {
+            unsafe {
+                let mut style = GetWindowLongPtrW(handle, GWL_EXSTYLE) | WS_EX_LAYERED;
+                if click_through {
+                    style |= WS_EX_TRANSPARENT;
+                } else {
+                    style &= !WS_EX_TRANSPARENT;
+                }
+                SetWindowLongPtrW(handle, GWL_EXSTYLE, style);
+                if SetLayeredWindowAttributes(handle, 0, (opacity.clamp(0.0, 1.0) * 255.0) as u8, LWA_ALPHA) == 0 {
+                    return Err(String::from("SetLayeredWindowAttributes failed"));
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// On X11 the opacity is a hint for the compositor and click-through is an empty input shape.
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::os::raw::c_void;
+    use x11rb::{
+        connection::Connection,
+        protocol::{shape::{self, ConnectionExt as _}, xproto::{Atom, AtomEnum, ClipOrdering, ConnectionExt as _, PropMode}},
+        rust_connection::RustConnection,
+        wrapper::ConnectionExt as _,
+        NONE,
+    };
+
+    pub struct Display {
+        connection: RustConnection,
+        opacity_atom: Atom,
+    }
+
+    impl Display {
+        pub fn open() -> Result<Self, String> {
+            let (connection, _) = x11rb::connect(None).map_err(|e| e.to_string())?;
+            let opacity_atom = connection
+                .intern_atom(false, b"_NET_WM_WINDOW_OPACITY").map_err(|e| e.to_string())?
+                .reply().map_err(|e| e.to_string())?
+                .atom;
+            Ok(Display { connection, opacity_atom })
+        }
+
+        pub fn apply(&self, handle: *mut c_void, opacity: f32, click_through: bool) -> Result<(), String> {
+            let window = handle as usize as u32;
+            let connection = &self.connection;
+
+            let value = (opacity.clamp(0.0, 1.0) as f64 * u32::MAX as f64) as u32;
+            connection.change_property32(PropMode::REPLACE, window, self.opacity_atom, AtomEnum::CARDINAL, &[value]).map_err(|e| e.to_string())?;
+
+            if click_through {
+                connection.shape_rectangles(shape::SO::SET, shape::SK::INPUT, ClipOrdering::UNSORTED, window, 0, 0, &[]).map_err(|e| e.to_string())?;
+            } else {
+                // Without a mask the input shape is the whole window again
+                connection.shape_mask(shape::SO::SET, shape::SK::INPUT, window, 0, 0, NONE).map_err(|e| e.to_string())?;
+            }
+            connection.flush().map_err(|e| e.to_string())?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+mod platform {
+    use std::os::raw::c_void;
+
+    pub struct Display;
+
+    impl Display {
+        pub fn open() -> Result<Self, String> {
+            Err(String::from("Pins are not supported on this platform"))
+        }
+
+        pub fn apply(&self, _handle: *mut c_void, _opacity: f32, _click_through: bool) -> Result<(), String> {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,259 @@
+use std::{env, sync::mpsc::{self, Receiver}, thread, time::{Duration, Instant}};
+use image::RgbaImage;
+
+/// How full screenshots are taken on Linux.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    /// The desktop portal on Wayland, the screenshots crate elsewhere, each one falling back
+    /// to the other when it fails
+    Auto,
+    Screenshots,
+    Portal,
+}
+
+impl Backend {
+    pub const ALL: [Backend; 3] = [Backend::Auto, Backend::Screenshots, Backend::Portal];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Backend::Auto => "Automatic",
+            Backend::Screenshots => "Direct (X11)",
+            Backend::Portal => "Desktop portal",
+        }
+    }
+}
+
+/// Wayland compositors only hand out screenshots through the portal.
+pub fn is_wayland() -> bool {
+    env::var_os("WAYLAND_DISPLAY").is_some() || env::var("XDG_SESSION_TYPE").is_ok_and(|t| t == "wayland")
+}
+
+/// A request without dialog answers right away, past this the main window is shown again
+/// so that the request can be cancelled
+pub const SHOW_WINDOW_AFTER: Duration = Duration::from_secs(3);
+/// A portal that doesn't answer in this time is considered broken, the user may be picking
+/// an area in its dialog until then
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// A screenshot requested to the portal, which may show its own dialog, so it runs on its
+/// own thread. A request that is given up leaves the thread waiting, its answer is dropped.
+pub struct PortalCapture {
+    receiver: Receiver<Result<Option<RgbaImage>, String>>,
+    started: Instant,
+    window_shown: bool,
+    pub result: Option<Result<Option<RgbaImage>, String>>,
+}
+
+impl PortalCapture {
+    pub fn start(interactive: bool) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(platform::request_screenshot(interactive));
+        });
+        PortalCapture { receiver, started: Instant::now(), window_shown: false, result: None }
+    }
+
+    /// Returns true once, when the request has been waiting long enough to show the main
+    /// window again.
+    pub fn show_window(&mut self) -> bool {
+        if self.window_shown || self.started.elapsed() < SHOW_WINDOW_AFTER {
+            return false;
+        }
+        self.window_shown = true;
+        true
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Returns true once, when the result is ready or the portal took too long.
+    pub fn poll(&mut self) -> bool {
+        match self.receiver.try_recv() {
+            Ok(result) => {
+                self.result = Some(result);
+                true
+            },
+            Err(mpsc::TryRecvError::Empty) if self.started.elapsed() > TIMEOUT => {
+                self.result = Some(Err(format!("The portal did not answer in {} seconds", TIMEOUT.as_secs())));
+                true
+            },
+            Err(mpsc::TryRecvError::Empty) => false,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                self.result = Some(Err(String::from("The portal request stopped")));
+                true
+            }
+        }
+    }
+}
+
+/// The portal is a D-Bus service of the Linux desktops.
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::{collections::HashMap, path::PathBuf, time::{SystemTime, UNIX_EPOCH}};
+    use image::RgbaImage;
+    use zbus::{blocking::{Connection, Proxy}, zvariant::{OwnedObjectPath, OwnedValue, Value}};
+
+    const DESTINATION: &str = "org.freedesktop.portal.Desktop";
+    const PATH: &str = "/org/freedesktop/portal/desktop";
+
+    /// Local path of a `file://` URI, with the percent-encoded bytes decoded.
+    fn file_uri_to_path(uri: &str) -> Result<PathBuf, String> {
+        let encoded = uri.strip_prefix("file://").ok_or_else(|| format!("Not a local file: {}", uri))?;
+        let mut bytes = Vec::new();
+        let mut rest = encoded.as_bytes();
+        while let Some((&byte, tail)) = rest.split_first() {
+            let decoded = if byte == b'%' && tail.len() >= 2 {
+                std::str::from_utf8(&tail[..2]).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            } else {
+                None
+            };
+            match decoded {
+                Some(decoded) => {
+                    bytes.push(decoded);
+                    rest = &tail[2..];
+                },
+                None => {
+                    bytes.push(byte);
+                    rest = tail;
+                }
+            }
+        }
+        Ok(PathBuf::from(String::from_utf8_lossy(&bytes).to_string()))
+    }
+
+    /// Calls `org.freedesktop.portal.Screenshot.Screenshot` and waits for the `Response` of the
+    /// request. Ok(None) when the user cancelled it. The session bus comes from
+    /// DBUS_SESSION_BUS_ADDRESS, so a mock portal on a private bus works too.
+    pub fn request_screenshot(interactive: bool) -> Result<Option<RgbaImage>, String> {
+        let connection = Connection::session().map_err(|e| format!("No session bus -> {}", e))?;
+
+        // The request object path is known in advance from the token, so the response can be
+        // subscribed to before the call and can't be missed
+        let token = format!("rust_screenshot_{}", SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or_default());
+        let sender = connection.unique_name().ok_or("No unique name on the session bus")?.as_str().trim_start_matches(':').replace('.', "_");
+        let request_path = format!("{}/request/{}/{}", PATH, sender, token);
+
+        let request = Proxy::new(&connection, DESTINATION, request_path.as_str(), "org.freedesktop.portal.Request").map_err(|e| e.to_string())?;
+        let mut responses = request.receive_signal("Response").map_err(|e| e.to_string())?;
+
+        let screenshot = Proxy::new(&connection, DESTINATION, PATH, "org.freedesktop.portal.Screenshot").map_err(|e| e.to_string())?;
+        let mut options: HashMap<&str, Value> = HashMap::new();
+        options.insert("handle_token", Value::from(token.as_str()));
+        options.insert("interactive", Value::from(interactive));
+        options.insert("modal", Value::from(true));
+        let handle: OwnedObjectPath = screenshot
+            .call("Screenshot", &("", options))
+            .map_err(|e| format!("The Screenshot portal is not available -> {}", e))?;
+        let handle_request;
+        if handle.as_str() != request_path {
+            // Old portals ignore the token, the response comes on the returned handle
+            handle_request = Proxy::new(&connection, DESTINATION, handle.as_str(), "org.freedesktop.portal.Request").map_err(|e| e.to_string())?;
+            responses = handle_request.receive_signal("Response").map_err(|e| e.to_string())?;
+        }
+
+        let message = responses.next().ok_or("The portal closed the request without a response")?;
+        let (response, results): (u32, HashMap<String, OwnedValue>) = message.body().map_err(|e| e.to_string())?;
+        match response {
+            0 => (),
+            1 => return Ok(None),
+            _ => return Err(String::from("The portal refused the screenshot")),
+        }
+
+        let uri = match results.get("uri").map(|value| &**value) {
+            Some(Value::Str(uri)) => uri.to_string(),
+            _ => return Err(String::from("The portal response has no uri")),
+        };
+        let path = file_uri_to_path(&uri)?;
+        let image = image::open(&path).map_err(|e| format!("Cannot open {} -> {:?}", path.display(), e))?;
+        Ok(Some(image.to_rgba8()))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::{collections::VecDeque, env, fs};
+        use image::Rgba;
+        use zbus::{blocking::ConnectionBuilder, dbus_interface, fdo, MessageHeader};
+        use crate::upload::uri_encode;
+        use super::*;
+
+        /// Answers every Screenshot call with the next response code and uri, like the portal
+        /// does: a `Response` signal on the request object predicted from the handle token.
+        struct MockScreenshot {
+            responses: VecDeque<(u32, String)>,
+        }
+
+        #[dbus_interface(name = "org.freedesktop.portal.Screenshot")]
+        impl MockScreenshot {
+            async fn screenshot(
+                &mut self,
+                #[zbus(connection)] connection: &zbus::Connection,
+                #[zbus(header)] header: MessageHeader<'_>,
+                _parent_window: &str,
+                options: HashMap<&str, Value<'_>>,
+            ) -> fdo::Result<OwnedObjectPath> {
+                let sender = header.sender().map_err(fdo::Error::ZBus)?.ok_or(fdo::Error::Failed(String::from("No sender")))?.to_owned();
+                let token = match options.get("handle_token") {
+                    Some(Value::Str(token)) => token.to_string(),
+                    _ => return Err(fdo::Error::InvalidArgs(String::from("No handle_token"))),
+                };
+                let request_path = format!("{}/request/{}/{}", PATH, sender.trim_start_matches(':').replace('.', "_"), token);
+
+                let (response, uri) = self.responses.pop_front().ok_or(fdo::Error::Failed(String::from("No response left")))?;
+                let mut results: HashMap<&str, Value> = HashMap::new();
+                if response == 0 {
+                    results.insert("uri", Value::from(uri.as_str()));
+                }
+                connection
+                    .emit_signal(Some(sender.clone()), request_path.as_str(), "org.freedesktop.portal.Request", "Response", &(response, results))
+                    .await
+                    .map_err(fdo::Error::ZBus)?;
+                OwnedObjectPath::try_from(request_path).map_err(|e| fdo::Error::ZBus(e.into()))
+            }
+        }
+
+        #[test]
+        fn decodes_file_uris() {
+            assert_eq!(file_uri_to_path("file:///tmp/a%20b/%C3%A9t%C3%A9.png"), Ok(PathBuf::from("/tmp/a b/été.png")));
+            // Invalid escapes are kept as they are
+            assert_eq!(file_uri_to_path("file:///tmp/100%zz%2"), Ok(PathBuf::from("/tmp/100%zz%2")));
+            assert!(file_uri_to_path("https://example.com/a.png").is_err());
+        }
+
+        /// Needs a session bus without a real portal, e.g. `dbus-run-session -- cargo test`.
+        /// Skipped when DBUS_SESSION_BUS_ADDRESS is not set.
+        #[test]
+        fn mock_portal_responses() {
+            if env::var_os("DBUS_SESSION_BUS_ADDRESS").is_none() {
+                println!("No session bus, skipping");
+                return;
+            }
+            let directory = env::temp_dir().join("rust-screenshot portal test");
+            fs::create_dir_all(&directory).unwrap();
+            let path = directory.join("shot 1 é.png");
+            RgbaImage::from_pixel(7, 5, Rgba([10, 20, 30, 255])).save(&path).unwrap();
+            let uri = format!("file://{}", uri_encode(path.to_str().unwrap(), false));
+
+            let responses = VecDeque::from([(0, uri), (1, String::new()), (2, String::new())]);
+            let _portal = ConnectionBuilder::session().unwrap()
+                .name(DESTINATION).unwrap()
+                .serve_at(PATH, MockScreenshot { responses }).unwrap()
+                .build().unwrap();
+
+            assert_eq!(request_screenshot(false), Ok(Some(RgbaImage::from_pixel(7, 5, Rgba([10, 20, 30, 255])))));
+            assert_eq!(request_screenshot(true), Ok(None));
+            assert_eq!(request_screenshot(false), Err(String::from("The portal refused the screenshot")));
+
+            fs::remove_dir_all(&directory).unwrap();
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use image::RgbaImage;
+
+    pub fn request_screenshot(_interactive: bool) -> Result<Option<RgbaImage>, String> {
+        Err(String::from("The desktop portal is only available on Linux"))
+    }
+}
@@ -0,0 +1,256 @@
+use std::{fs::File, io::BufWriter, sync::{atomic::{AtomicBool, Ordering}, mpsc::{self, Receiver}, Arc}, thread, time::{Duration, Instant}};
+use chrono::prelude::*;
+use image::{imageops::{self, FilterType::Triangle}, RgbaImage};
+
+use crate::{capture::CaptureTarget, watermark::{self, Watermark}};
+
+/// Frames are kept uncompressed until the end, the recording stops once they take this much memory
+const MAX_FRAMES_BYTES: usize = 1 << 30;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecordingFormat {
+    Gif,
+    Apng,
+}
+
+impl RecordingFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            RecordingFormat::Gif => ".gif",
+            RecordingFormat::Apng => ".png",
+        }
+    }
+}
+
+pub enum RecordingEvent {
+    Frame(u32),
+    /// The recording stopped early, the frames captured so far are still encoded
+    Stopped(String),
+    Encoding,
+    Saved(String),
+    Error(String),
+}
+
+/// Part of a frame that differs from the previous one, with the unchanged pixels made
+/// transparent so that the previous frame shows through.
+pub struct DiffFrame {
+    pub image: RgbaImage,
+    pub left: u32,
+    pub top: u32,
+    pub delay_ms: u32,
+}
+
+/// Turns a list of same-sized frames into the smallest rectangles that need to be redrawn.
+/// Frames identical to the previous one only extend its duration.
+pub fn diff_frames(frames: &[RgbaImage], delay_ms: u32) -> Vec<DiffFrame> {
+    let mut diff: Vec<DiffFrame> = Vec::new();
+
+    for (i, frame) in frames.iter().enumerate() {
+        if i == 0 {
+            diff.push(DiffFrame { image: frame.clone(), left: 0, top: 0, delay_ms });
+            continue;
+        }
+
+        let previous = &frames[i - 1];
+        let mut min_x = u32::MAX;
+        let mut min_y = u32::MAX;
+        let mut max_x = 0;
+        let mut max_y = 0;
+        for (x, y, pixel) in frame.enumerate_pixels() {
+            if previous.get_pixel(x, y) != pixel {
+                min_x = min_x.min(x);
+                min_y = min_y.min(y);
+                max_x = max_x.max(x);
+                max_y = max_y.max(y);
+            }
+        }
+
+        if min_x == u32::MAX {
+            diff.last_mut().unwrap().delay_ms += delay_ms;
+            continue;
+        }
+
+        let mut image = imageops::crop_imm(frame, min_x, min_y, max_x - min_x + 1, max_y - min_y + 1).to_image();
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            if previous.get_pixel(min_x + x, min_y + y) == pixel {
+                pixel.0 = [0, 0, 0, 0];
+            }
+        }
+        diff.push(DiffFrame { image, left: min_x, top: min_y, delay_ms });
+    }
+
+    diff
+}
+
+pub fn encode_gif(frames: &[RgbaImage], delay_ms: u32, path: &str) -> Result<(), String> {
+    let (width, height) = frames[0].dimensions();
+    let file = File::create(path).map_err(|e| format!("{:?}", e))?;
+    let mut encoder = gif::Encoder::new(BufWriter::new(file), width as u16, height as u16, &[]).map_err(|e| format!("{:?}", e))?;
+    encoder.set_repeat(gif::Repeat::Infinite).map_err(|e| format!("{:?}", e))?;
+
+    for diff in diff_frames(frames, delay_ms) {
+        let (frame_width, frame_height) = diff.image.dimensions();
+        let mut pixels = diff.image.into_raw();
+        // Each frame gets its own NeuQuant palette (speed 10 is the usual quality/speed tradeoff)
+        let mut frame = gif::Frame::from_rgba_speed(frame_width as u16, frame_height as u16, &mut pixels, 10);
+        frame.left = diff.left as u16;
+        frame.top = diff.top as u16;
+        frame.delay = (diff.delay_ms / 10).min(u16::MAX as u32) as u16;
+        frame.dispose = gif::DisposalMethod::Keep;
+        encoder.write_frame(&frame).map_err(|e| format!("{:?}", e))?;
+    }
+
+    Ok(())
+}
+
+pub fn encode_apng(frames: &[RgbaImage], delay_ms: u32, path: &str) -> Result<(), String> {
+    let (width, height) = frames[0].dimensions();
+    let diff = diff_frames(frames, delay_ms);
+
+    let file = File::create(path).map_err(|e| format!("{:?}", e))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(diff.len() as u32, 0).map_err(|e| format!("{:?}", e))?;
+    let mut writer = encoder.write_header().map_err(|e| format!("{:?}", e))?;
+
+    for frame in diff {
+        // The position has to be reset first, the new size is validated against the current one
+        writer.reset_frame_position().map_err(|e| format!("{:?}", e))?;
+        writer.set_frame_dimension(frame.image.width(), frame.image.height()).map_err(|e| format!("{:?}", e))?;
+        writer.set_frame_position(frame.left, frame.top).map_err(|e| format!("{:?}", e))?;
+        writer.set_frame_delay(frame.delay_ms.min(u16::MAX as u32) as u16, 1000).map_err(|e| format!("{:?}", e))?;
+        writer.set_blend_op(png::BlendOp::Over).map_err(|e| format!("{:?}", e))?;
+        writer.write_image_data(frame.image.as_raw()).map_err(|e| format!("{:?}", e))?;
+    }
+
+    writer.finish().map_err(|e| format!("{:?}", e))
+}
+
+/// A recording running on a background thread: the target is captured `fps` times per
+/// second until stopped or `max_duration` is reached, then encoded to `directory`.
+pub struct Recording {
+    stop: Arc<AtomicBool>,
+    receiver: Receiver<RecordingEvent>,
+    pub frames: u32,
+    pub notice: Option<String>,
+    pub encoding: bool,
+    pub saved: Option<String>,
+    pub error: Option<String>,
+}
+
+impl Recording {
+    pub fn start(target: CaptureTarget, format: RecordingFormat, fps: u32, max_duration: f32, max_width: u32, directory: String, watermark: Option<Watermark>) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel();
+        let thread_stop = stop.clone();
+
+        thread::spawn(move || {
+            let frame_time = Duration::from_secs_f32(1.0 / fps as f32);
+            let started = Instant::now();
+            let mut frames: Vec<RgbaImage> = Vec::new();
+            let mut next_frame = Instant::now();
+
+            while !thread_stop.load(Ordering::Relaxed) && started.elapsed().as_secs_f32() < max_duration {
+                let now = Instant::now();
+                if now < next_frame {
+                    thread::sleep(next_frame - now);
+                }
+                next_frame += frame_time;
+
+                let image = match target.capture() {
+                    Some(image) => image,
+                    None => continue,
+                };
+
+                // Frames wider than the limit are scaled down, every frame must match the first one
+                let (width, height) = match frames.first() {
+                    Some(first) => first.dimensions(),
+                    None if image.width() > max_width => (max_width, image.height() * max_width / image.width()),
+                    None => image.dimensions(),
+                };
+                let image = if image.dimensions() != (width, height) {
+                    imageops::resize(&image, width, height, Triangle)
+                } else {
+                    image
+                };
+
+                frames.push(image);
+                let _ = sender.send(RecordingEvent::Frame(frames.len() as u32));
+
+                if frames.len() * width as usize * height as usize * 4 >= MAX_FRAMES_BYTES {
+                    let _ = sender.send(RecordingEvent::Stopped(format!(
+                        "Stopped after {} frames, the memory limit of {} MiB was reached. Lower the FPS, duration or max width to record longer",
+                        frames.len(), MAX_FRAMES_BYTES >> 20
+                    )));
+                    break;
+                }
+            }
+
+            if frames.is_empty() {
+                let _ = sender.send(RecordingEvent::Error(String::from("No frame captured")));
+                return;
+            }
+
+            let _ = sender.send(RecordingEvent::Encoding);
+            // All the frames have the same size, the stamp is rendered once
+            if let Some(watermark) = &watermark {
+                match watermark::render_stamp(watermark, frames[0].width()) {
+                    Ok(stamp) => frames.iter_mut().for_each(|frame| watermark::overlay_stamp(frame, &stamp, watermark.position)),
+                    Err(error) => println!("Error while applying the watermark -> {}", error),
+                }
+            }
+            let path = format!("{}/rust_recording_{}{}", directory, Utc::now().format("%d-%m-%Y_%H-%M-%S"), format.extension());
+            let delay_ms = 1000 / fps;
+            let result = match format {
+                RecordingFormat::Gif => encode_gif(&frames, delay_ms, &path),
+                RecordingFormat::Apng => encode_apng(&frames, delay_ms, &path),
+            };
+            match result {
+                Ok(_) => { let _ = sender.send(RecordingEvent::Saved(path)); },
+                Err(error) => { let _ = sender.send(RecordingEvent::Error(error)); },
+            }
+        });
+
+        Recording {
+            stop,
+            receiver,
+            frames: 0,
+            notice: None,
+            encoding: false,
+            saved: None,
+            error: None,
+        }
+    }
+
+    /// Collects what the recording thread did since the last call.
+    pub fn poll(&mut self) {
+        while let Ok(event) = self.receiver.try_recv() {
+            match event {
+                RecordingEvent::Frame(frames) => self.frames = frames,
+                RecordingEvent::Stopped(notice) => {
+                    println!("Recording stopped -> {}", notice);
+                    self.notice = Some(notice);
+                },
+                RecordingEvent::Encoding => self.encoding = true,
+                RecordingEvent::Saved(path) => {
+                    self.encoding = false;
+                    self.saved = Some(path);
+                },
+                RecordingEvent::Error(error) => {
+                    println!("Error during recording -> {}", error);
+                    self.encoding = false;
+                    self.error = Some(error);
+                },
+            }
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.saved.is_some() || self.error.is_some()
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,218 @@
+use std::{sync::{atomic::{AtomicBool, Ordering}, mpsc::{self, Receiver}, Arc}, thread, time::{Duration, Instant}};
+use image::{imageops::{self, FilterType}, RgbaImage};
+
+use crate::capture::CaptureTarget;
+
+/// Rows are compared on a copy reduced to this many columns, enough to tell lines of text apart
+const PROFILE_COLUMNS: u32 = 64;
+/// Smallest overlap accepted between two frames, in rows
+const MIN_OVERLAP: usize = 16;
+/// Mean difference (0-255) under which two rows are considered the same
+const MAX_ROW_ERROR: f32 = 2.0;
+/// An overlap made only of flat rows (blank background) matches anywhere, it needs at least
+/// this many rows with some content
+const MIN_TEXTURED_ROWS: usize = 4;
+
+/// Grayscale copy reduced to `PROFILE_COLUMNS`, one row of values per image row.
+struct Profile {
+    columns: usize,
+    values: Vec<f32>,
+    height: usize,
+}
+
+impl Profile {
+    fn new(image: &RgbaImage) -> Self {
+        let columns = PROFILE_COLUMNS.min(image.width()).max(1);
+        let reduced = imageops::resize(image, columns, image.height(), FilterType::Triangle);
+        let values = reduced.pixels().map(|p| 0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32).collect();
+        Profile { columns: columns as usize, values, height: image.height() as usize }
+    }
+
+    fn row(&self, y: usize) -> &[f32] {
+        &self.values[y * self.columns..(y + 1) * self.columns]
+    }
+
+    fn is_textured(&self, y: usize) -> bool {
+        let row = self.row(y);
+        row.windows(2).map(|w| (w[0] - w[1]).abs()).sum::<f32>() / row.len() as f32 > 1.0
+    }
+}
+
+fn row_error(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| (x - y).abs()).sum::<f32>() / a.len() as f32
+}
+
+/// Rows that don't move between the two frames at the top and at the bottom, like sticky
+/// headers and footers. None when the frames are identical.
+fn fixed_rows(a: &Profile, b: &Profile) -> Option<(usize, usize)> {
+    let height = a.height.min(b.height);
+    let same = |ya: usize, yb: usize| row_error(a.row(ya), b.row(yb)) < MAX_ROW_ERROR;
+    let header = (0..height).take_while(|&y| same(y, y)).count();
+    if header == height {
+        return None;
+    }
+    let footer = (1..=height).take_while(|&i| same(a.height - i, b.height - i)).count();
+    Some((header.min(height / 3), footer.min(height / 3)))
+}
+
+/// Number of rows at the bottom of the scrolling part of `a` (between `header` and `footer`)
+/// that are repeated at the top of the scrolling part of `b`.
+fn find_overlap(a: &Profile, b: &Profile, header: usize, footer: usize) -> Option<usize> {
+    let a_body = a.height.saturating_sub(header + footer);
+    let b_body = b.height.saturating_sub(header + footer);
+    let mut best: Option<(usize, f32)> = None;
+
+    for overlap in MIN_OVERLAP..=a_body.min(b_body) {
+        let a_start = header + a_body - overlap;
+        let textured = (0..overlap).filter(|&j| b.is_textured(header + j)).take(MIN_TEXTURED_ROWS).count();
+        if textured < MIN_TEXTURED_ROWS {
+            continue;
+        }
+
+        let mut total = 0.0;
+        for j in 0..overlap {
+            total += row_error(a.row(a_start + j), b.row(header + j));
+            // The total only grows, once worse than the best candidate it can't win
+            if total / overlap as f32 > best.map_or(MAX_ROW_ERROR, |(_, error)| error) {
+                break;
+            }
+        }
+        let error = total / overlap as f32;
+        // On equal errors the larger overlap wins, it duplicates less
+        if error < MAX_ROW_ERROR && !best.is_some_and(|(_, best_error)| error > best_error) {
+            best = Some((overlap, error));
+        }
+    }
+    best.map(|(overlap, _)| overlap)
+}
+
+/// Joins frames of a region scrolled downwards into one tall image. Sticky headers and
+/// footers are kept once, at the top and at the bottom. Frames must have the same width.
+pub fn stitch(frames: &[RgbaImage]) -> Result<RgbaImage, String> {
+    let first = frames.first().ok_or("No frames to stitch")?;
+    if frames.iter().any(|f| f.width() != first.width()) {
+        return Err(String::from("All the frames must have the same width"));
+    }
+
+    let profiles: Vec<Profile> = frames.iter().map(Profile::new).collect();
+    // The same header and footer for every frame, the smallest found between two frames
+    let fixed: Vec<(usize, usize)> = profiles.windows(2).filter_map(|p| fixed_rows(&p[0], &p[1])).collect();
+    let header = fixed.iter().map(|(h, _)| *h).min().unwrap_or(0);
+    let footer = fixed.iter().map(|(_, f)| *f).min().unwrap_or(0);
+
+    let width = first.width();
+    let row_bytes = width as usize * 4;
+    let rows = |frame: &RgbaImage, from: usize, to: usize| frame.as_raw()[from * row_bytes..to * row_bytes].to_vec();
+
+    let mut stitched = rows(first, 0, first.height() as usize - footer);
+    for (index, pair) in profiles.windows(2).enumerate() {
+        let frame = &frames[index + 1];
+        let overlap = match find_overlap(&pair[0], &pair[1], header, footer) {
+            Some(overlap) => overlap,
+            None => {
+                println!("No overlap found before frame {}, it is appended as is", index + 1);
+                0
+            }
+        };
+        let from = (header + overlap).min(frame.height() as usize - footer);
+        stitched.extend(rows(frame, from, frame.height() as usize - footer));
+    }
+    let last = frames.last().unwrap();
+    stitched.extend(rows(last, last.height() as usize - footer, last.height() as usize));
+
+    let height = (stitched.len() / row_bytes) as u32;
+    RgbaImage::from_raw(width, height, stitched).ok_or_else(|| String::from("Invalid stitched image"))
+}
+
+/// `rust-screenshot stitch OUTPUT FRAME...`, frames in scrolling order.
+pub fn run_cli(args: &[String]) -> i32 {
+    if args.len() < 3 {
+        println!("Usage: rust-screenshot stitch OUTPUT FRAME FRAME...");
+        return 2;
+    }
+
+    let mut frames = Vec::new();
+    for file in &args[1..] {
+        match image::open(file) {
+            Ok(image) => frames.push(image.to_rgba8()),
+            Err(error) => {
+                println!("Error while opening {} -> {:?}", file, error);
+                return 1;
+            }
+        }
+    }
+
+    match stitch(&frames).map(|image| image.save(&args[0]).map_err(|e| format!("{:?}", e))) {
+        Ok(Ok(_)) => {
+            println!("Saved {}", args[0]);
+            0
+        },
+        Ok(Err(error)) | Err(error) => {
+            println!("Error while stitching -> {}", error);
+            1
+        }
+    }
+}
+
+enum ScrollEvent {
+    Frame,
+    Finished(Result<RgbaImage, String>),
+}
+
+/// Captures the target repeatedly while the user scrolls it, keeping only the frames that
+/// changed, and stitches them when stopped or when nothing changed for `idle_timeout` seconds.
+pub struct ScrollCapture {
+    stop: Arc<AtomicBool>,
+    receiver: Receiver<ScrollEvent>,
+    pub frames: u32,
+    pub result: Option<Result<RgbaImage, String>>,
+}
+
+impl ScrollCapture {
+    pub fn start(target: CaptureTarget, idle_timeout: f32) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel();
+        let thread_stop = stop.clone();
+
+        thread::spawn(move || {
+            let mut frames: Vec<RgbaImage> = Vec::new();
+            let mut last_change = Instant::now();
+
+            while !thread_stop.load(Ordering::Relaxed) && last_change.elapsed().as_secs_f32() < idle_timeout {
+                match target.capture() {
+                    Some(image) if frames.last() != Some(&image) => {
+                        frames.push(image);
+                        last_change = Instant::now();
+                        let _ = sender.send(ScrollEvent::Frame);
+                    },
+                    Some(_) => (),
+                    None => println!("Capture failed during scrolling capture"),
+                }
+                thread::sleep(Duration::from_millis(150));
+            }
+
+            let _ = sender.send(ScrollEvent::Finished(stitch(&frames)));
+        });
+
+        ScrollCapture { stop, receiver, frames: 0, result: None }
+    }
+
+    /// Returns true once, when the stitched image is ready.
+    pub fn poll(&mut self) -> bool {
+        let mut finished = false;
+        while let Ok(event) = self.receiver.try_recv() {
+            match event {
+                ScrollEvent::Frame => self.frames += 1,
+                ScrollEvent::Finished(result) => {
+                    self.result = Some(result);
+                    finished = true;
+                }
+            }
+        }
+        finished
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,85 @@
+use image::{imageops::{self, FilterType}, Rgba, RgbaImage};
+
+use crate::crop::Selection;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transform {
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    FlipHorizontal,
+    FlipVertical,
+    Resize(u32, u32, FilterType),
+    /// Padding added on top, right, bottom and left, filled with the color
+    ExtendCanvas(u32, u32, u32, u32, Rgba<u8>),
+}
+
+pub const FILTERS: [(FilterType, &str); 5] = [
+    (FilterType::Nearest, "Nearest"),
+    (FilterType::Triangle, "Bilinear"),
+    (FilterType::CatmullRom, "Bicubic"),
+    (FilterType::Gaussian, "Gaussian"),
+    (FilterType::Lanczos3, "Lanczos3"),
+];
+
+pub fn filter_name(filter: FilterType) -> &'static str {
+    FILTERS.iter().find(|(f, _)| *f == filter).map_or("", |(_, name)| name)
+}
+
+pub fn apply(image: &RgbaImage, transform: Transform) -> RgbaImage {
+    match transform {
+        Transform::Rotate90 => imageops::rotate90(image),
+        Transform::Rotate180 => imageops::rotate180(image),
+        Transform::Rotate270 => imageops::rotate270(image),
+        Transform::FlipHorizontal => imageops::flip_horizontal(image),
+        Transform::FlipVertical => imageops::flip_vertical(image),
+        Transform::Resize(width, height, filter) => imageops::resize(image, width.max(1), height.max(1), filter),
+        Transform::ExtendCanvas(top, right, bottom, left, color) => {
+            let mut canvas = RgbaImage::from_pixel(image.width() + left + right, image.height() + top + bottom, color);
+            imageops::replace(&mut canvas, image, left as i64, top as i64);
+            canvas
+        }
+    }
+}
+
+/// The transform to apply to a whole image of `size` pixels, and where the `crop` selection
+/// ends up, so that the crop shows the same result as transforming the cropped image alone.
+/// None for resizes and canvas extensions, they apply to the cropped pixels only.
+pub fn on_original(transform: Transform, crop: Selection, size: (u32, u32)) -> Option<(Transform, Selection)> {
+    let (width, height) = size;
+    let Selection { x, y, width: w, height: h } = crop;
+    let selection = match transform {
+        Transform::Rotate90 => Selection { x: height - y - h, y: x, width: h, height: w },
+        Transform::Rotate180 => Selection { x: width - x - w, y: height - y - h, width: w, height: h },
+        Transform::Rotate270 => Selection { x: y, y: width - x - w, width: h, height: w },
+        Transform::FlipHorizontal => Selection { x: width - x - w, y, width: w, height: h },
+        Transform::FlipVertical => Selection { x, y: height - y - h, width: w, height: h },
+        // A size typed for a small crop would blow the whole image up far past the crop
+        Transform::Resize(..) | Transform::ExtendCanvas(..) => return None,
+    };
+    Some((transform, selection))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crop;
+
+    #[test]
+    fn crop_follows_the_transform() {
+        let image = RgbaImage::from_fn(40, 30, |x, y| Rgba([x as u8, y as u8, (x * y) as u8, 255]));
+        let selection = Selection { x: 5, y: 7, width: 12, height: 9 };
+        for transform in [Transform::Rotate90, Transform::Rotate180, Transform::Rotate270, Transform::FlipHorizontal, Transform::FlipVertical] {
+            let (whole, moved) = on_original(transform, selection, image.dimensions()).unwrap();
+            assert_eq!(crop::crop(&apply(&image, whole), moved), apply(&crop::crop(&image, selection), transform), "{:?}", transform);
+        }
+    }
+
+    #[test]
+    fn resize_and_extend_keep_to_the_crop() {
+        // The default target size on a tiny crop of a 4K capture must not scale the whole capture
+        let selection = Selection { x: 1000, y: 500, width: 20, height: 20 };
+        assert!(on_original(Transform::Resize(1280, 720, FilterType::Triangle), selection, (3840, 2160)).is_none());
+        assert!(on_original(Transform::ExtendCanvas(1, 1, 1, 1, Rgba([0, 0, 0, 255])), selection, (3840, 2160)).is_none());
+    }
+}
@@ -0,0 +1,309 @@
+use std::{io::Cursor, sync::mpsc::{self, Receiver}, thread};
+use chrono::{format::{Item, StrftimeItems}, prelude::*};
+use image::{DynamicImage, ImageOutputFormat, RgbaImage};
+
+/// Encodes the image the same way it would be saved with the given extension.
+pub fn encode_image(image: &RgbaImage, extension: &str) -> Result<(Vec<u8>, &'static str), String> {
+    let mut bytes = Cursor::new(Vec::new());
+    let content_type = match extension {
+        // Jpeg has no alpha channel
+        ".jpeg" | ".jpg" => {
+            DynamicImage::ImageRgba8(image.clone()).to_rgb8().write_to(&mut bytes, ImageOutputFormat::Jpeg(90)).map_err(|e| format!("{:?}", e))?;
+            "image/jpeg"
+        },
+        ".gif" => {
+            image.write_to(&mut bytes, ImageOutputFormat::Gif).map_err(|e| format!("{:?}", e))?;
+            "image/gif"
+        },
+        _ => {
+            image.write_to(&mut bytes, ImageOutputFormat::Png).map_err(|e| format!("{:?}", e))?;
+            "image/png"
+        }
+    };
+    Ok((bytes.into_inner(), content_type))
+}
+
+/// Replaces the chrono placeholders (%Y, %m...) of a path template. An invalid placeholder
+/// would make the formatting panic, in that case the template is used as is.
+pub fn format_template(template: &str) -> String {
+    let items: Vec<Item> = StrftimeItems::new(template).collect();
+    if items.iter().any(|item| matches!(item, Item::Error)) {
+        return template.to_string();
+    }
+    Utc::now().format_with_items(items.into_iter()).to_string()
+}
+
+/// Percent-encodes everything but the unreserved characters, as SigV4 and WebDAV paths
+/// require. Slashes are kept when `encode_slash` is false.
+pub fn uri_encode(value: &str, encode_slash: bool) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// A destination for captures. Returns the link to share, when the destination provides one.
+pub trait Uploader: Send {
+    fn upload(&self, data: &[u8], file_name: &str, content_type: &str) -> Result<Option<String>, String>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UploadDestination {
+    Http,
+    S3,
+    WebDav,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResponseRule {
+    None,
+    JsonPointer,
+    Regex,
+}
+
+/// POSTs the image to a user configured URL, either as a multipart form or as the raw body.
+#[derive(Debug, Clone)]
+pub struct HttpUploader {
+    pub url: String,
+    pub multipart: bool,
+    pub field_name: String,
+    /// One `Name: value` per line
+    pub headers: String,
+    pub response_rule: ResponseRule,
+    /// JSON pointer (e.g. `/data/link`) or regex (first group, or whole match, is the link)
+    pub response_pattern: String,
+}
+
+impl Default for HttpUploader {
+    fn default() -> Self {
+        HttpUploader {
+            url: String::from("http://localhost:8080/upload"),
+            multipart: true,
+            field_name: String::from("file"),
+            headers: String::new(),
+            response_rule: ResponseRule::None,
+            response_pattern: String::new(),
+        }
+    }
+}
+
+impl HttpUploader {
+    pub fn parse_headers(&self) -> Vec<(String, String)> {
+        self.headers
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .filter(|(name, _)| !name.is_empty())
+            .collect()
+    }
+
+    pub fn extract_link(&self, response: &str) -> Result<Option<String>, String> {
+        match self.response_rule {
+            ResponseRule::None => Ok(None),
+            ResponseRule::JsonPointer => {
+                let json: serde_json::Value = serde_json::from_str(response).map_err(|e| format!("Invalid JSON response: {}", e))?;
+                match json.pointer(&self.response_pattern) {
+                    Some(serde_json::Value::String(link)) => Ok(Some(link.clone())),
+                    Some(value) => Ok(Some(value.to_string())),
+                    None => Err(format!("{} not found in the response", self.response_pattern)),
+                }
+            },
+            ResponseRule::Regex => {
+                let regex = regex::Regex::new(&self.response_pattern).map_err(|e| format!("Invalid regex: {}", e))?;
+                match regex.captures(response) {
+                    Some(captures) => Ok(Some(captures.get(1).unwrap_or(captures.get(0).unwrap()).as_str().to_string())),
+                    None => Err(String::from("The regex does not match the response")),
+                }
+            }
+        }
+    }
+}
+
+impl Uploader for HttpUploader {
+    fn upload(&self, data: &[u8], file_name: &str, content_type: &str) -> Result<Option<String>, String> {
+        let mut request = ureq::post(&self.url);
+        for (name, value) in self.parse_headers() {
+            request = request.set(&name, &value);
+        }
+
+        let response = if self.multipart {
+            let boundary = format!("rust-screenshot-{}", Utc::now().timestamp_nanos_opt().unwrap_or_default());
+            let mut body = Vec::new();
+            body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+            body.extend_from_slice(format!("Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n", self.field_name, file_name).as_bytes());
+            body.extend_from_slice(format!("Content-Type: {}\r\n\r\n", content_type).as_bytes());
+            body.extend_from_slice(data);
+            body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+
+            request
+                .set("Content-Type", &format!("multipart/form-data; boundary={}", boundary))
+                .send_bytes(&body)
+        } else {
+            request.set("Content-Type", content_type).send_bytes(data)
+        };
+
+        match response {
+            Ok(response) => {
+                let text = response.into_string().map_err(|e| format!("{:?}", e))?;
+                self.extract_link(&text)
+            },
+            Err(ureq::Error::Status(code, response)) => Err(format!("Server answered {}: {}", code, response.into_string().unwrap_or_default())),
+            Err(error) => Err(format!("{}", error)),
+        }
+    }
+}
+
+/// An upload running on a background thread.
+pub struct UploadTask {
+    receiver: Receiver<Result<Option<String>, String>>,
+    pub result: Option<Result<Option<String>, String>>,
+}
+
+impl UploadTask {
+    pub fn start(uploader: Box<dyn Uploader>, data: Vec<u8>, file_name: String, content_type: &'static str) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(uploader.upload(&data, &file_name, content_type));
+        });
+
+        UploadTask { receiver, result: None }
+    }
+
+    /// Returns true the first time the result is available.
+    pub fn poll(&mut self) -> bool {
+        if self.result.is_none() {
+            if let Ok(result) = self.receiver.try_recv() {
+                self.result = Some(result);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::{BufRead, BufReader, Read, Write}, net::TcpListener, thread::JoinHandle};
+    use super::*;
+
+    /// Request received by the mock server: the header lines and the body.
+    struct Request {
+        headers: Vec<String>,
+        body: Vec<u8>,
+    }
+
+    impl Request {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers.iter().find_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                key.eq_ignore_ascii_case(name).then(|| value.trim())
+            })
+        }
+    }
+
+    /// Answers one request on a local port with `response` and hands back what it received.
+    fn mock_server(response: &'static str) -> (String, JoinHandle<Request>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/upload", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut headers = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim_end().is_empty() {
+                    break;
+                }
+                headers.push(line.trim_end().to_string());
+            }
+            let mut request = Request { headers, body: Vec::new() };
+            let length = request.header("Content-Length").map_or(0, |length| length.parse().unwrap());
+            request.body.resize(length, 0);
+            reader.read_exact(&mut request.body).unwrap();
+
+            write!(reader.get_mut(), "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", response.len(), response).unwrap();
+            request
+        });
+        (url, server)
+    }
+
+    #[test]
+    fn multipart_body_and_headers() {
+        let (url, server) = mock_server("ok");
+        let uploader = HttpUploader {
+            url,
+            field_name: String::from("image"),
+            headers: String::from("Authorization: Bearer secret\nX-Custom:  42 \nnot a header"),
+            ..Default::default()
+        };
+        assert_eq!(uploader.upload(b"PNGDATA", "shot.png", "image/png"), Ok(None));
+
+        let request = server.join().unwrap();
+        assert!(request.headers[0].starts_with("POST /upload "));
+        assert_eq!(request.header("Authorization"), Some("Bearer secret"));
+        assert_eq!(request.header("X-Custom"), Some("42"));
+        let boundary = request.header("Content-Type").unwrap().strip_prefix("multipart/form-data; boundary=").unwrap().to_string();
+        let expected = format!(
+            "--{0}\r\nContent-Disposition: form-data; name=\"image\"; filename=\"shot.png\"\r\nContent-Type: image/png\r\n\r\nPNGDATA\r\n--{0}--\r\n",
+            boundary
+        );
+        assert_eq!(String::from_utf8(request.body).unwrap(), expected);
+    }
+
+    #[test]
+    fn raw_body() {
+        let (url, server) = mock_server("ok");
+        let uploader = HttpUploader { url, multipart: false, ..Default::default() };
+        assert_eq!(uploader.upload(b"JPEGDATA", "shot.jpg", "image/jpeg"), Ok(None));
+
+        let request = server.join().unwrap();
+        assert_eq!(request.header("Content-Type"), Some("image/jpeg"));
+        assert_eq!(request.body, b"JPEGDATA");
+    }
+
+    #[test]
+    fn json_pointer_link() {
+        let (url, server) = mock_server(r#"{"data": {"link": "https://example.com/a.png", "id": 7}}"#);
+        let mut uploader = HttpUploader {
+            url,
+            response_rule: ResponseRule::JsonPointer,
+            response_pattern: String::from("/data/link"),
+            ..Default::default()
+        };
+        assert_eq!(uploader.upload(b"x", "a.png", "image/png"), Ok(Some(String::from("https://example.com/a.png"))));
+        server.join().unwrap();
+
+        // Values other than strings are returned as JSON
+        uploader.response_pattern = String::from("/data/id");
+        assert_eq!(uploader.extract_link(r#"{"data": {"id": 7}}"#), Ok(Some(String::from("7"))));
+        uploader.response_pattern = String::from("/missing");
+        assert!(uploader.extract_link(r#"{"data": {}}"#).is_err());
+        assert!(uploader.extract_link("not json").is_err());
+    }
+
+    #[test]
+    fn regex_link() {
+        let (url, server) = mock_server("Uploaded to <a href=\"https://example.com/b.png\">here</a>");
+        let mut uploader = HttpUploader {
+            url,
+            response_rule: ResponseRule::Regex,
+            response_pattern: String::from(r#"href="([^"]+)""#),
+            ..Default::default()
+        };
+        assert_eq!(uploader.upload(b"x", "b.png", "image/png"), Ok(Some(String::from("https://example.com/b.png"))));
+        server.join().unwrap();
+
+        // Without a group the whole match is the link
+        uploader.response_pattern = String::from(r"https://\S+\.png");
+        assert_eq!(uploader.extract_link("see https://example.com/c.png"), Ok(Some(String::from("https://example.com/c.png"))));
+        assert_eq!(uploader.extract_link("no link here"), Err(String::from("The regex does not match the response")));
+        uploader.response_pattern = String::from("(");
+        assert!(uploader.extract_link("anything").is_err());
+    }
+}
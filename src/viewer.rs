@@ -0,0 +1,93 @@
+use eframe::{egui::{self, emath::RectTransform, PointerButton, Pos2, Rect, Response, Sense, TextureId, Vec2}, epaint::Color32};
+
+pub const MIN_ZOOM: f32 = 0.05;
+pub const MAX_ZOOM: f32 = 32.0;
+
+/// Zoom and pan state of an image view. A zoom of 1 shows one image pixel per physical
+/// screen pixel, whatever the display scaling.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ZoomPan {
+    pub zoom: f32,
+    /// Position of the image's top left corner inside the view, in points
+    pub offset: Vec2,
+    /// Keep fitting the image to the view, until the user zooms or pans
+    pub fit: bool,
+}
+
+impl Default for ZoomPan {
+    fn default() -> Self {
+        ZoomPan { zoom: 1.0, offset: Vec2::ZERO, fit: true }
+    }
+}
+
+impl ZoomPan {
+    pub fn fit_to_window(&mut self) {
+        self.fit = true;
+    }
+
+    pub fn actual_size(&mut self) {
+        self.zoom = 1.0;
+        self.offset = Vec2::ZERO;
+        self.fit = false;
+    }
+
+    /// Multiplies the zoom keeping the image point under `anchor` (relative to the view) still.
+    pub fn zoom_by(&mut self, factor: f32, anchor: Vec2) {
+        let new_zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.offset = anchor - (anchor - self.offset) * (new_zoom / self.zoom);
+        self.zoom = new_zoom;
+        self.fit = false;
+    }
+
+    /// Toolbar with fit, 100%, zoom out/in and the current percentage.
+    pub fn ui_controls(&mut self, ui: &mut egui::Ui, view_size: Vec2) {
+        ui.horizontal(|ui| {
+            if ui.selectable_label(self.fit, "⛶ Fit").on_hover_text("Fit to window (Ctrl+0)").clicked() {
+                self.fit_to_window();
+            }
+            if ui.button("1:1").on_hover_text("Actual pixels (Ctrl+1)").clicked() {
+                self.actual_size();
+            }
+            if ui.button("➖").clicked() {
+                self.zoom_by(1.0 / 1.25, view_size / 2.0);
+            }
+            if ui.button("➕").clicked() {
+                self.zoom_by(1.25, view_size / 2.0);
+            }
+            ui.label(format!("{:.0}%", self.zoom * 100.0));
+        });
+    }
+
+    /// Fills the available space with the image: the mouse wheel (or pinch) zooms around the
+    /// cursor and dragging with `pan_button` pans. Returns the response of the view and the
+    /// transform from image pixel coordinates to the screen.
+    pub fn show(&mut self, ui: &mut egui::Ui, texture: TextureId, image_size: Vec2, pan_button: PointerButton) -> (Response, RectTransform) {
+        let (response, painter) = ui.allocate_painter(ui.available_size().max(Vec2::splat(100.0)), Sense::click_and_drag());
+        let rect = response.rect;
+        let pixels_per_point = ui.ctx().pixels_per_point();
+        // Image size in points at zoom 1
+        let actual_size = image_size / pixels_per_point;
+
+        if self.fit {
+            self.zoom = (rect.width() / actual_size.x).min(rect.height() / actual_size.y).clamp(MIN_ZOOM, 1.0);
+            self.offset = (rect.size() - actual_size * self.zoom) / 2.0;
+        }
+
+        if let Some(hover) = response.hover_pos() {
+            let (scroll, pinch) = ui.input(|i| (i.scroll_delta.y, i.zoom_delta()));
+            let factor = pinch * (scroll * 0.002).exp();
+            if factor != 1.0 {
+                self.zoom_by(factor, hover - rect.min);
+            }
+        }
+        if response.dragged_by(pan_button) {
+            self.offset += response.drag_delta();
+            self.fit = false;
+        }
+
+        let image_rect = Rect::from_min_size(rect.min + self.offset, actual_size * self.zoom);
+        painter.image(texture, image_rect, Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0)), Color32::WHITE);
+
+        (response, RectTransform::from_to(Rect::from_min_size(Pos2::ZERO, image_size), image_rect))
+    }
+}
@@ -0,0 +1,239 @@
+use ab_glyph::{point, Font, FontRef, Glyph, PxScale, ScaleFont};
+use eframe::egui::FontDefinitions;
+use image::{imageops::{self, FilterType}, Rgba, RgbaImage};
+
+/// Where an exported image goes, each has its own watermark.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Output {
+    Save = 0,
+    Clipboard = 1,
+    Upload = 2,
+}
+
+impl Output {
+    pub const ALL: [Output; 3] = [Output::Save, Output::Clipboard, Output::Upload];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Output::Save => "Save",
+            Output::Clipboard => "Clipboard",
+            Output::Upload => "Upload",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stamp {
+    Text,
+    Logo,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Position {
+    TopLeft,
+    TopRight,
+    Center,
+    BottomLeft,
+    BottomRight,
+    Tiled,
+}
+
+impl Position {
+    pub const ALL: [Position; 6] = [
+        Position::TopLeft,
+        Position::TopRight,
+        Position::Center,
+        Position::BottomLeft,
+        Position::BottomRight,
+        Position::Tiled,
+    ];
+}
+
+#[derive(Debug, Clone)]
+pub struct Watermark {
+    pub enabled: bool,
+    pub stamp: Stamp,
+    pub text: String,
+    /// Unmultiplied RGBA
+    pub color: [u8; 4],
+    /// PNG with transparency works best
+    pub logo_path: String,
+    pub position: Position,
+    /// 0 is invisible, 1 fully opaque
+    pub opacity: f32,
+    /// Degrees, counterclockwise
+    pub rotation: f32,
+    /// Width of the stamp as a fraction of the image width
+    pub scale: f32,
+}
+
+impl Default for Watermark {
+    fn default() -> Self {
+        Watermark {
+            enabled: false,
+            stamp: Stamp::Text,
+            text: String::from("CONFIDENTIAL"),
+            color: [255, 0, 0, 255],
+            logo_path: String::new(),
+            position: Position::Center,
+            opacity: 0.3,
+            rotation: 30.0,
+            scale: 0.5,
+        }
+    }
+}
+
+/// Runs `f` with the font egui ships with.
+fn with_font<R>(f: impl FnOnce(&FontRef) -> R) -> Result<R, String> {
+    let fonts = FontDefinitions::default();
+    let data = fonts.font_data.get("Ubuntu-Light").ok_or("The default font is missing")?;
+    let font = FontRef::try_from_slice(&data.font).map_err(|e| format!("{:?}", e))?;
+    Ok(f(&font))
+}
+
+fn text_width(font: &FontRef, scale: PxScale, text: &str) -> f32 {
+    let scaled = font.as_scaled(scale);
+    let mut caret = 0.0;
+    let mut previous = None;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            caret += scaled.kern(previous, id);
+        }
+        caret += scaled.h_advance(id);
+        previous = Some(id);
+    }
+    caret
+}
+
+fn draw_text(font: &FontRef, scale: PxScale, text: &str, color: [u8; 4], width: u32) -> RgbaImage {
+    let scaled = font.as_scaled(scale);
+    let height = (scaled.ascent() - scaled.descent()).ceil() as u32;
+    let [r, g, b, a] = color;
+    let mut stamp = RgbaImage::from_pixel(width.max(1), height.max(1), Rgba([r, g, b, 0]));
+
+    let mut caret = 0.0;
+    let mut previous = None;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            caret += scaled.kern(previous, id);
+        }
+        let glyph: Glyph = id.with_scale_and_position(scale, point(caret, scaled.ascent()));
+        caret += scaled.h_advance(id);
+        previous = Some(id);
+
+        if let Some(outlined) = font.outline_glyph(glyph) {
+            let bounds = outlined.px_bounds();
+            outlined.draw(|x, y, coverage| {
+                let x = bounds.min.x as i32 + x as i32;
+                let y = bounds.min.y as i32 + y as i32;
+                if x >= 0 && y >= 0 && (x as u32) < stamp.width() && (y as u32) < stamp.height() {
+                    let pixel = stamp.get_pixel_mut(x as u32, y as u32);
+                    let alpha = (coverage.min(1.0) * a as f32) as u8;
+                    pixel[3] = pixel[3].max(alpha);
+                }
+            });
+        }
+    }
+    stamp
+}
+
+/// Renders the text `width` pixels wide.
+fn render_text(text: &str, color: [u8; 4], width: u32) -> Result<RgbaImage, String> {
+    with_font(|font| {
+        // Measure at a reference size, then scale so the text fills the requested width
+        let text_width = text_width(font, PxScale::from(100.0), text).max(1.0);
+        draw_text(font, PxScale::from(100.0 * width as f32 / text_width), text, color, width)
+    })
+}
+
+/// Renders the text with a font of `size` pixels, as wide as it needs.
+pub fn render_label(text: &str, color: [u8; 4], size: f32) -> Result<RgbaImage, String> {
+    with_font(|font| {
+        let scale = PxScale::from(size);
+        draw_text(font, scale, text, color, text_width(font, scale, text).ceil() as u32)
+    })
+}
+
+fn load_logo(path: &str, width: u32) -> Result<RgbaImage, String> {
+    let logo = image::open(path).map_err(|e| format!("Cannot open the logo {} -> {:?}", path, e))?.to_rgba8();
+    let height = (logo.height() as f32 * width as f32 / logo.width().max(1) as f32).round() as u32;
+    Ok(imageops::resize(&logo, width.max(1), height.max(1), FilterType::Triangle))
+}
+
+/// Rotates around the center into a canvas big enough to hold the whole stamp.
+fn rotate(stamp: &RgbaImage, degrees: f32) -> RgbaImage {
+    if degrees % 360.0 == 0.0 {
+        return stamp.clone();
+    }
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (width, height) = (stamp.width() as f32, stamp.height() as f32);
+    let rotated_width = (width * cos.abs() + height * sin.abs()).ceil() as u32;
+    let rotated_height = (width * sin.abs() + height * cos.abs()).ceil() as u32;
+
+    let (cx, cy) = (width / 2.0, height / 2.0);
+    let (rcx, rcy) = (rotated_width as f32 / 2.0, rotated_height as f32 / 2.0);
+    RgbaImage::from_fn(rotated_width, rotated_height, |x, y| {
+        // Inverse mapping, y grows downwards so counterclockwise on screen is a negative angle
+        let dx = x as f32 + 0.5 - rcx;
+        let dy = y as f32 + 0.5 - rcy;
+        let sx = dx * cos - dy * sin + cx;
+        let sy = dx * sin + dy * cos + cy;
+        if sx < 0.0 || sy < 0.0 || sx >= width || sy >= height {
+            Rgba([0, 0, 0, 0])
+        } else {
+            *stamp.get_pixel(sx as u32, sy as u32)
+        }
+    })
+}
+
+/// The stamp sized for an image `width` pixels wide, with the opacity and rotation applied.
+/// The frames of a recording share one.
+pub fn render_stamp(watermark: &Watermark, width: u32) -> Result<RgbaImage, String> {
+    let stamp_width = ((width as f32 * watermark.scale).round() as u32).max(1);
+    let mut stamp = match watermark.stamp {
+        Stamp::Text => render_text(&watermark.text, watermark.color, stamp_width)?,
+        Stamp::Logo => load_logo(&watermark.logo_path, stamp_width)?,
+    };
+    for pixel in stamp.pixels_mut() {
+        pixel[3] = (pixel[3] as f32 * watermark.opacity.clamp(0.0, 1.0)).round() as u8;
+    }
+    Ok(rotate(&stamp, watermark.rotation))
+}
+
+/// Draws a stamp from `render_stamp` on the image at the given position.
+pub fn overlay_stamp(image: &mut RgbaImage, stamp: &RgbaImage, position: Position) {
+    let (stamp_width, stamp_height) = (stamp.width() as i64, stamp.height() as i64);
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    let margin = width.min(height) / 50;
+    match position {
+        Position::TopLeft => imageops::overlay(image, stamp, margin, margin),
+        Position::TopRight => imageops::overlay(image, stamp, width - stamp_width - margin, margin),
+        Position::Center => imageops::overlay(image, stamp, (width - stamp_width) / 2, (height - stamp_height) / 2),
+        Position::BottomLeft => imageops::overlay(image, stamp, margin, height - stamp_height - margin),
+        Position::BottomRight => imageops::overlay(image, stamp, width - stamp_width - margin, height - stamp_height - margin),
+        Position::Tiled => {
+            // Brick pattern, every other row shifted by half a tile
+            let (step_x, step_y) = (stamp_width * 3 / 2, stamp_height * 2);
+            let mut row = 0;
+            let mut y = -stamp_height / 2;
+            while y < height {
+                let mut x = if row % 2 == 0 { 0 } else { -step_x / 2 };
+                while x < width {
+                    imageops::overlay(image, stamp, x, y);
+                    x += step_x.max(1);
+                }
+                y += step_y.max(1);
+                row += 1;
+            }
+        }
+    }
+}
+
+/// Stamps the watermark on a copy of the image.
+pub fn apply(image: &RgbaImage, watermark: &Watermark) -> Result<RgbaImage, String> {
+    let mut stamped = image.clone();
+    overlay_stamp(&mut stamped, &render_stamp(watermark, image.width())?, watermark.position);
+    Ok(stamped)
+}
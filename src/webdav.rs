@@ -0,0 +1,116 @@
+use base64::Engine;
+
+use crate::upload::{format_template, uri_encode, Uploader};
+
+/// Uploads to a WebDAV collection (e.g. Nextcloud `https://host/remote.php/dav/files/USER`).
+#[derive(Debug, Clone)]
+pub struct WebDavUploader {
+    pub url: String,
+    pub username: String,
+    pub password: String,
+    /// Directories under `url`, with chrono placeholders, e.g. `Screenshots/%Y/%m/`
+    pub path_template: String,
+    /// Create a Nextcloud public link after the upload
+    pub share: bool,
+}
+
+impl Default for WebDavUploader {
+    fn default() -> Self {
+        WebDavUploader {
+            url: String::from("https://localhost/remote.php/dav/files/user"),
+            username: String::new(),
+            password: String::new(),
+            path_template: String::from("Screenshots/%Y/%m/"),
+            share: false,
+        }
+    }
+}
+
+impl WebDavUploader {
+    fn authorization(&self) -> String {
+        let credentials = format!("{}:{}", self.username, self.password);
+        format!("Basic {}", base64::engine::general_purpose::STANDARD.encode(credentials))
+    }
+
+    fn collection_url(&self) -> String {
+        self.url.trim_end_matches('/').to_string()
+    }
+
+    fn request(&self, method: &str, url: &str) -> ureq::Request {
+        ureq::request(method, url).set("Authorization", &self.authorization())
+    }
+
+    /// MKCOL every directory of the path, one level at a time. Existing ones answer 405.
+    fn create_collections(&self, directories: &[&str]) -> Result<(), String> {
+        let mut url = self.collection_url();
+        for directory in directories {
+            url = format!("{}/{}", url, uri_encode(directory, true));
+            match self.request("MKCOL", &url).call() {
+                Ok(_) | Err(ureq::Error::Status(405, _)) => (),
+                Err(ureq::Error::Status(code, response)) => {
+                    return Err(format!("Cannot create {} ({}): {}", url, code, response.into_string().unwrap_or_default()));
+                },
+                Err(error) => return Err(format!("{}", error)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Nextcloud share API: server root and path of the file relative to the user's files.
+    fn share_target(&self, path: &str) -> Option<(String, String)> {
+        let url = self.collection_url();
+        let (server, rest) = url.split_once("/remote.php/dav/files/")?;
+        // rest is USER[/folder...]
+        let user_root = rest.split_once('/').map_or("", |(_, folder)| folder);
+        let file_path = if user_root.is_empty() { format!("/{}", path) } else { format!("/{}/{}", user_root, path) };
+        Some((server.to_string(), file_path))
+    }
+
+    fn create_share(&self, path: &str) -> Result<String, String> {
+        let (server, file_path) = self.share_target(path).ok_or(String::from("Public links need a Nextcloud URL (.../remote.php/dav/files/USER)"))?;
+        let response = self
+            .request("POST", &format!("{}/ocs/v2.php/apps/files_sharing/api/v1/shares?format=json", server))
+            .set("OCS-APIRequest", "true")
+            .send_form(&[("path", &file_path), ("shareType", "3")]);
+
+        match response {
+            Ok(response) => {
+                let text = response.into_string().map_err(|e| format!("{:?}", e))?;
+                let json: serde_json::Value = serde_json::from_str(&text).map_err(|e| format!("Invalid JSON response: {}", e))?;
+                json.pointer("/ocs/data/url")
+                    .and_then(|url| url.as_str())
+                    .map(|url| url.to_string())
+                    .ok_or(String::from("No link in the share response"))
+            },
+            Err(ureq::Error::Status(code, response)) => Err(format!("Share failed ({}): {}", code, response.into_string().unwrap_or_default())),
+            Err(error) => Err(format!("{}", error)),
+        }
+    }
+}
+
+impl Uploader for WebDavUploader {
+    fn upload(&self, data: &[u8], file_name: &str, content_type: &str) -> Result<Option<String>, String> {
+        let directory = format_template(&self.path_template);
+        let directories: Vec<&str> = directory.split('/').filter(|d| !d.is_empty()).collect();
+        self.create_collections(&directories)?;
+
+        let mut path: Vec<&str> = directories.clone();
+        path.push(file_name);
+        let path = path.join("/");
+        let url = format!("{}/{}", self.collection_url(), uri_encode(&path, false));
+
+        match self.request("PUT", &url).set("Content-Type", content_type).send_bytes(data) {
+            Ok(_) => (),
+            Err(ureq::Error::Status(code, response)) => {
+                return Err(format!("Server answered {}: {}", code, response.into_string().unwrap_or_default()));
+            },
+            Err(error) => return Err(format!("{}", error)),
+        }
+
+        if self.share {
+            self.create_share(&path).map(Some)
+        } else {
+            Ok(Some(url))
+        }
+    }
+}
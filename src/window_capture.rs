@@ -0,0 +1,436 @@
+use std::{sync::mpsc::{self, Receiver}, thread};
+use image::{imageops, RgbaImage};
+
+/// A top-level application window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowInfo {
+    pub id: u32,
+    pub title: String,
+    pub class: String,
+    /// Position on the root window and size, without decorations
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    /// Window added around it by the window manager for the title bar and borders
+    pub frame: Option<u32>,
+    /// Client-side shadow around the content (left, right, top, bottom), from `_GTK_FRAME_EXTENTS`
+    pub shadow: [u32; 4],
+}
+
+impl WindowInfo {
+    pub fn label(&self) -> String {
+        let title = if self.title.is_empty() { "(untitled)" } else { &self.title };
+        format!("{} - {} ({}x{})", title, self.class, self.width, self.height)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindowCaptureOptions {
+    /// Include the title bar and borders of the window manager
+    pub decorations: bool,
+    /// Cut the client-side shadow and the transparent border around the window
+    pub remove_shadow: bool,
+    /// Read the window's own contents with XComposite, even if other windows cover it
+    pub composite: bool,
+}
+
+impl Default for WindowCaptureOptions {
+    fn default() -> Self {
+        WindowCaptureOptions { decorations: true, remove_shadow: true, composite: true }
+    }
+}
+
+/// Cuts the rows and columns at the borders without any fully opaque pixel, like the soft
+/// shadow compositors draw around windows with an alpha channel.
+pub fn trim_transparent(image: &RgbaImage) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let opaque_row = |y: u32| (0..width).any(|x| image.get_pixel(x, y)[3] == 255);
+    let opaque_column = |x: u32| (0..height).any(|y| image.get_pixel(x, y)[3] == 255);
+
+    let top = match (0..height).find(|&y| opaque_row(y)) {
+        Some(top) => top,
+        None => return image.clone(),
+    };
+    let bottom = (0..height).rev().find(|&y| opaque_row(y)).unwrap();
+    let left = (0..width).find(|&x| opaque_column(x)).unwrap();
+    let right = (0..width).rev().find(|&x| opaque_column(x)).unwrap();
+    imageops::crop_imm(image, left, top, right - left + 1, bottom - top + 1).to_image()
+}
+
+/// Lists the top-level windows, in the window manager's order.
+pub fn list_windows() -> Result<Vec<WindowInfo>, String> {
+    platform::list_windows()
+}
+
+pub fn capture_window(window: &WindowInfo, options: WindowCaptureOptions) -> Result<RgbaImage, String> {
+    platform::capture_window(window, options)
+}
+
+/// A window capture running on its own thread, a window redirected for XComposite needs a
+/// moment to paint its covered parts.
+pub struct WindowCapture {
+    receiver: Receiver<Result<RgbaImage, String>>,
+    pub window: WindowInfo,
+}
+
+impl WindowCapture {
+    pub fn start(window: WindowInfo, options: WindowCaptureOptions) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let thread_window = window.clone();
+        thread::spawn(move || {
+            let _ = sender.send(capture_window(&thread_window, options));
+        });
+        WindowCapture { receiver, window }
+    }
+
+    /// The result, once the capture is done.
+    pub fn poll(&self) -> Option<Result<RgbaImage, String>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(mpsc::TryRecvError::Empty) => None,
+            Err(mpsc::TryRecvError::Disconnected) => Some(Err(String::from("The window capture stopped"))),
+        }
+    }
+}
+
+/// Window capture talks to the X server directly, the screenshots crate only grabs displays.
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::{thread, time::Duration};
+    use image::{imageops, RgbaImage};
+    use x11rb::{
+        connection::Connection,
+        protocol::{
+            composite::{ConnectionExt as _, Redirect},
+            xproto::{Atom, AtomEnum, ConnectionExt as _, ImageFormat, ImageOrder, MapState, Window},
+        },
+        rust_connection::RustConnection,
+        wrapper::ConnectionExt as _,
+    };
+
+    use super::{trim_transparent, WindowCaptureOptions, WindowInfo};
+
+    fn atom(connection: &RustConnection, name: &str) -> Result<Atom, String> {
+        Ok(connection.intern_atom(false, name.as_bytes()).map_err(|e| e.to_string())?.reply().map_err(|e| e.to_string())?.atom)
+    }
+
+    fn property(connection: &RustConnection, window: Window, property: Atom) -> Result<Vec<u8>, String> {
+        let reply = connection
+            .get_property(false, window, property, AtomEnum::ANY, 0, u32::MAX)
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map_err(|e| e.to_string())?;
+        Ok(reply.value)
+    }
+
+    fn property32(connection: &RustConnection, window: Window, property: Atom) -> Result<Vec<u32>, String> {
+        let reply = connection
+            .get_property(false, window, property, AtomEnum::ANY, 0, u32::MAX)
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map_err(|e| e.to_string())?;
+        Ok(reply.value32().map(|values| values.collect()).unwrap_or_default())
+    }
+
+    fn title(connection: &RustConnection, window: Window) -> Result<String, String> {
+        let name = property(connection, window, atom(connection, "_NET_WM_NAME")?)?;
+        let name = if name.is_empty() { property(connection, window, AtomEnum::WM_NAME.into())? } else { name };
+        Ok(String::from_utf8_lossy(&name).to_string())
+    }
+
+    /// WM_CLASS holds the instance and the class name, separated by a zero byte.
+    fn class(connection: &RustConnection, window: Window) -> Result<String, String> {
+        let value = property(connection, window, AtomEnum::WM_CLASS.into())?;
+        let mut parts = value.split(|b| *b == 0).filter(|part| !part.is_empty());
+        let instance = parts.next().unwrap_or_default();
+        Ok(String::from_utf8_lossy(parts.next().unwrap_or(instance)).to_string())
+    }
+
+    /// Ancestor of the window that is a direct child of the root, the window manager frame.
+    fn frame(connection: &RustConnection, window: Window, root: Window) -> Result<Option<Window>, String> {
+        let mut current = window;
+        loop {
+            let parent = connection.query_tree(current).map_err(|e| e.to_string())?.reply().map_err(|e| e.to_string())?.parent;
+            if parent == root || parent == x11rb::NONE {
+                return Ok(if current == window { None } else { Some(current) });
+            }
+            current = parent;
+        }
+    }
+
+    /// Without a window manager publishing `_NET_CLIENT_LIST` (a bare X server, Xvfb) the
+    /// mapped children of the root are the application windows.
+    fn client_windows(connection: &RustConnection, root: Window) -> Result<Vec<Window>, String> {
+        let clients = property32(connection, root, atom(connection, "_NET_CLIENT_LIST")?)?;
+        if !clients.is_empty() {
+            return Ok(clients);
+        }
+
+        let children = connection.query_tree(root).map_err(|e| e.to_string())?.reply().map_err(|e| e.to_string())?.children;
+        let mut clients = Vec::new();
+        for child in children {
+            let attributes = connection.get_window_attributes(child).map_err(|e| e.to_string())?.reply().map_err(|e| e.to_string())?;
+            if attributes.map_state == MapState::VIEWABLE && !attributes.override_redirect {
+                clients.push(child);
+            }
+        }
+        Ok(clients)
+    }
+
+    pub fn list_windows() -> Result<Vec<WindowInfo>, String> {
+        let (connection, screen) = x11rb::connect(None).map_err(|e| e.to_string())?;
+        let root = connection.setup().roots[screen].root;
+        let gtk_frame_extents = atom(&connection, "_GTK_FRAME_EXTENTS")?;
+
+        let mut windows = Vec::new();
+        for id in client_windows(&connection, root)? {
+            // Windows can disappear while they are listed
+            let geometry = match connection.get_geometry(id).map_err(|e| e.to_string())?.reply() {
+                Ok(geometry) => geometry,
+                Err(_) => continue,
+            };
+            let position = connection.translate_coordinates(id, root, 0, 0).map_err(|e| e.to_string())?.reply().map_err(|e| e.to_string())?;
+            let mut shadow = [0; 4];
+            for (side, value) in shadow.iter_mut().zip(property32(&connection, id, gtk_frame_extents)?) {
+                *side = value;
+            }
+
+            windows.push(WindowInfo {
+                id,
+                title: title(&connection, id)?,
+                class: class(&connection, id)?,
+                x: position.dst_x as i32,
+                y: position.dst_y as i32,
+                width: geometry.width as u32,
+                height: geometry.height as u32,
+                frame: frame(&connection, id, root)?,
+                shadow,
+            });
+        }
+        Ok(windows)
+    }
+
+    /// Converts 32 bit pixels to RGBA with the red, green and blue masks of their visual. Only
+    /// 32 bit deep windows have an alpha channel, in the bits left by the colors.
+    pub fn convert_pixels(data: &[u8], lsb_first: bool, masks: [u32; 3], has_alpha: bool, width: u32, height: u32) -> Option<RgbaImage> {
+        let channel = |pixel: u32, mask: u32| ((pixel & mask) >> mask.trailing_zeros()) as u8;
+        let alpha_mask = !(masks[0] | masks[1] | masks[2]);
+        let pixels = data.chunks_exact(4).take((width * height) as usize).flat_map(|bytes| {
+            let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+            let pixel = if lsb_first { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) };
+            let alpha = if has_alpha { channel(pixel, alpha_mask) } else { 255 };
+            [channel(pixel, masks[0]), channel(pixel, masks[1]), channel(pixel, masks[2]), alpha]
+        }).collect();
+        RgbaImage::from_raw(width, height, pixels)
+    }
+
+    /// Converts a ZPixmap image with 32 bits per pixel to RGBA, using the masks of the visual.
+    fn to_rgba(connection: &RustConnection, data: &[u8], depth: u8, visual: u32, width: u32, height: u32) -> Result<RgbaImage, String> {
+        let setup = connection.setup();
+        let format = setup.pixmap_formats.iter().find(|f| f.depth == depth).ok_or("Unknown pixmap format")?;
+        if format.bits_per_pixel != 32 {
+            return Err(format!("Unsupported pixel format ({} bits per pixel)", format.bits_per_pixel));
+        }
+        let visual = setup.roots.iter()
+            .flat_map(|s| &s.allowed_depths)
+            .flat_map(|d| &d.visuals)
+            .find(|v| v.visual_id == visual)
+            .ok_or("Unknown visual")?;
+
+        let masks = [visual.red_mask, visual.green_mask, visual.blue_mask];
+        convert_pixels(data, setup.image_byte_order == ImageOrder::LSB_FIRST, masks, depth == 32, width, height)
+            .ok_or_else(|| String::from("Incomplete image from the X server"))
+    }
+
+    /// The window's own pixmap, kept up to date by the server while it is redirected. Waits
+    /// for the application to repaint, so it runs off the UI thread (see `WindowCapture`).
+    fn capture_composite(connection: &RustConnection, window: Window, width: u16, height: u16) -> Result<RgbaImage, String> {
+        connection.composite_query_version(0, 2).map_err(|e| e.to_string())?.reply().map_err(|_| "XComposite is not available")?;
+        let attributes = connection.get_window_attributes(window).map_err(|e| e.to_string())?.reply().map_err(|e| e.to_string())?;
+
+        // Automatic redirection can be shared with a running compositor. A window redirected
+        // now gets its covered parts painted by the application shortly after.
+        connection.composite_redirect_window(window, Redirect::AUTOMATIC).map_err(|e| e.to_string())?;
+        connection.sync().map_err(|e| e.to_string())?;
+        thread::sleep(Duration::from_millis(100));
+
+        let pixmap = connection.generate_id().map_err(|e| e.to_string())?;
+        connection.composite_name_window_pixmap(window, pixmap).map_err(|e| e.to_string())?;
+        let image = connection.get_image(ImageFormat::Z_PIXMAP, pixmap, 0, 0, width, height, !0).map_err(|e| e.to_string())?.reply();
+        connection.free_pixmap(pixmap).map_err(|e| e.to_string())?;
+        connection.composite_unredirect_window(window, Redirect::AUTOMATIC).map_err(|e| e.to_string())?;
+        connection.flush().map_err(|e| e.to_string())?;
+
+        let image = image.map_err(|e| format!("Cannot read the window pixmap -> {}", e))?;
+        // Pixmaps have no visual, the window's one describes the pixels
+        to_rgba(connection, &image.data, image.depth, attributes.visual, width as u32, height as u32)
+    }
+
+    /// What is on screen at the window's place, covering windows included.
+    fn capture_geometry(connection: &RustConnection, root: Window, x: i32, y: i32, width: u32, height: u32) -> Result<RgbaImage, String> {
+        let root_geometry = connection.get_geometry(root).map_err(|e| e.to_string())?.reply().map_err(|e| e.to_string())?;
+        let min_x = x.max(0);
+        let min_y = y.max(0);
+        let max_x = (x + width as i32).min(root_geometry.width as i32);
+        let max_y = (y + height as i32).min(root_geometry.height as i32);
+        if max_x <= min_x || max_y <= min_y {
+            return Err(String::from("The window is off screen"));
+        }
+
+        let (width, height) = ((max_x - min_x) as u16, (max_y - min_y) as u16);
+        let image = connection
+            .get_image(ImageFormat::Z_PIXMAP, root, min_x as i16, min_y as i16, width, height, !0)
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map_err(|e| e.to_string())?;
+        to_rgba(connection, &image.data, image.depth, image.visual, width as u32, height as u32)
+    }
+
+    pub fn capture_window(window: &WindowInfo, options: WindowCaptureOptions) -> Result<RgbaImage, String> {
+        let (connection, screen) = x11rb::connect(None).map_err(|e| e.to_string())?;
+        let root = connection.setup().roots[screen].root;
+
+        let target = match window.frame {
+            Some(frame) if options.decorations => frame,
+            _ => window.id,
+        };
+        let geometry = connection.get_geometry(target).map_err(|e| e.to_string())?.reply().map_err(|e| format!("The window is gone -> {}", e))?;
+        let position = connection.translate_coordinates(target, root, 0, 0).map_err(|e| e.to_string())?.reply().map_err(|e| e.to_string())?;
+
+        let mut image = if options.composite {
+            capture_composite(&connection, target, geometry.width, geometry.height).or_else(|error| {
+                println!("Composite capture failed, falling back to the screen contents -> {}", error);
+                capture_geometry(&connection, root, position.dst_x as i32, position.dst_y as i32, geometry.width as u32, geometry.height as u32)
+            })?
+        } else {
+            capture_geometry(&connection, root, position.dst_x as i32, position.dst_y as i32, geometry.width as u32, geometry.height as u32)?
+        };
+
+        if options.remove_shadow {
+            // Client-side decorated windows draw their shadow inside the window
+            let [left, right, top, bottom] = window.shadow;
+            if target == window.id && left + right < image.width() && top + bottom < image.height() {
+                image = imageops::crop_imm(&image, left, top, image.width() - left - right, image.height() - top - bottom).to_image();
+            }
+            image = trim_transparent(&image);
+        }
+        Ok(image)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use image::RgbaImage;
+
+    use super::{WindowCaptureOptions, WindowInfo};
+
+    pub fn list_windows() -> Result<Vec<WindowInfo>, String> {
+        Err(String::from("Window capture is only available on X11"))
+    }
+
+    pub fn capture_window(_window: &WindowInfo, _options: WindowCaptureOptions) -> Result<RgbaImage, String> {
+        Err(String::from("Window capture is only available on X11"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+    use super::*;
+
+    #[test]
+    fn trim_transparent_borders() {
+        // Nothing opaque, nothing to trim against
+        let transparent = RgbaImage::from_pixel(10, 8, Rgba([0, 0, 0, 0]));
+        assert_eq!(trim_transparent(&transparent), transparent);
+
+        // A soft shadow around an opaque window
+        let mut shadowed = RgbaImage::from_pixel(20, 16, Rgba([0, 0, 0, 80]));
+        for y in 3..12 {
+            for x in 4..17 {
+                shadowed.put_pixel(x, y, Rgba([200, 100, 50, 255]));
+            }
+        }
+        let trimmed = trim_transparent(&shadowed);
+        assert_eq!(trimmed.dimensions(), (13, 9));
+        assert!(trimmed.pixels().all(|p| *p == Rgba([200, 100, 50, 255])));
+
+        let mut single = RgbaImage::from_pixel(5, 5, Rgba([255, 255, 255, 254]));
+        single.put_pixel(3, 1, Rgba([1, 2, 3, 255]));
+        assert_eq!(trim_transparent(&single), RgbaImage::from_pixel(1, 1, Rgba([1, 2, 3, 255])));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn convert_pixels_masks_and_byte_order() {
+        use super::platform::convert_pixels;
+
+        // 0xAARRGGBB, the usual TrueColor layout
+        let masks = [0x00ff0000, 0x0000ff00, 0x000000ff];
+        let pixel: u32 = 0x80112233;
+        let lsb = convert_pixels(&pixel.to_le_bytes(), true, masks, true, 1, 1).unwrap();
+        assert_eq!(*lsb.get_pixel(0, 0), Rgba([0x11, 0x22, 0x33, 0x80]));
+        let msb = convert_pixels(&pixel.to_be_bytes(), false, masks, true, 1, 1).unwrap();
+        assert_eq!(*msb.get_pixel(0, 0), Rgba([0x11, 0x22, 0x33, 0x80]));
+        // 24 bit deep windows leave garbage in the unused byte
+        let opaque = convert_pixels(&pixel.to_le_bytes(), true, masks, false, 1, 1).unwrap();
+        assert_eq!(*opaque.get_pixel(0, 0), Rgba([0x11, 0x22, 0x33, 255]));
+
+        // Blue in the high bits
+        let bgr = convert_pixels(&0x00aabbccu32.to_le_bytes(), true, [0x000000ff, 0x0000ff00, 0x00ff0000], false, 1, 1).unwrap();
+        assert_eq!(*bgr.get_pixel(0, 0), Rgba([0xcc, 0xbb, 0xaa, 255]));
+
+        assert!(convert_pixels(&[0; 4], true, masks, false, 2, 1).is_none());
+    }
+
+    /// Needs an X server without window manager, e.g. `xvfb-run cargo test`. Skipped when
+    /// DISPLAY is not set.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn capture_a_mapped_window() {
+        use std::{env, time::{Duration, Instant}};
+        use x11rb::{connection::Connection, protocol::xproto::{AtomEnum, ConnectionExt as _, CreateWindowAux, PropMode, WindowClass}, wrapper::ConnectionExt as _};
+
+        if env::var_os("DISPLAY").is_none() {
+            println!("No X server, skipping");
+            return;
+        }
+        let (connection, screen) = x11rb::connect(None).unwrap();
+        let screen = &connection.setup().roots[screen];
+        let window = connection.generate_id().unwrap();
+        connection.create_window(
+            screen.root_depth, window, screen.root, 30, 40, 120, 80, 0, WindowClass::INPUT_OUTPUT, 0,
+            &CreateWindowAux::new().background_pixel(0x00ff8000),
+        ).unwrap();
+        connection.change_property8(PropMode::REPLACE, window, AtomEnum::WM_NAME, AtomEnum::STRING, b"capture test").unwrap();
+        connection.change_property8(PropMode::REPLACE, window, AtomEnum::WM_CLASS, AtomEnum::STRING, b"test\0Test\0").unwrap();
+        connection.map_window(window).unwrap();
+        connection.sync().unwrap();
+
+        // Mapping is asynchronous, wait until the server lists the window as viewable
+        let started = Instant::now();
+        let info = loop {
+            if let Some(info) = list_windows().unwrap().into_iter().find(|w| w.id == window) {
+                break info;
+            }
+            assert!(started.elapsed() < Duration::from_secs(5), "The window was never listed");
+            thread::sleep(Duration::from_millis(50));
+        };
+        assert_eq!((info.title.as_str(), info.class.as_str()), ("capture test", "Test"));
+        assert_eq!((info.x, info.y, info.width, info.height), (30, 40, 120, 80));
+        assert_eq!(info.frame, None);
+
+        // Let the server paint the background before reading the screen
+        thread::sleep(Duration::from_millis(200));
+        for composite in [false, true] {
+            let options = WindowCaptureOptions { decorations: true, remove_shadow: true, composite };
+            let image = capture_window(&info, options).unwrap();
+            assert_eq!(image.dimensions(), (120, 80));
+            assert!(image.pixels().all(|p| *p == Rgba([0xff, 0x80, 0x00, 255])), "composite: {}", composite);
+        }
+
+        connection.destroy_window(window).unwrap();
+        connection.flush().unwrap();
+    }
+}